@@ -0,0 +1,45 @@
+use crate::command;
+use crate::config::{load_config, Check, CommandLine, Format, ProcessSpec};
+
+// Reports every problem found in the config and returns the exit code for
+// `spot-init check`.
+pub fn run(config_path: &str, format: Option<Format>) -> i32 {
+    let problems = match load_config(config_path, format) {
+        Ok(config) => {
+            let mut problems = config.problems();
+            for (name, spec) in &config.processes {
+                problems.extend(missing_executables(name, spec));
+            }
+            problems
+        }
+        Err(err) => vec![err],
+    };
+
+    if problems.is_empty() {
+        println!("{}: ok", config_path);
+        return 0;
+    }
+    for problem in &problems {
+        println!("{}: {}", config_path, problem);
+    }
+    1
+}
+
+fn missing_executables(name: &str, spec: &ProcessSpec) -> Vec<String> {
+    let mut command_lines = vec![&spec.command];
+    command_lines.extend(&spec.stop_command);
+    if let Some(Check::Exec(command_line)) = spec.ready.as_ref().map(|probe| &probe.check) {
+        command_lines.push(command_line);
+    }
+
+    command_lines
+        .into_iter()
+        .filter_map(
+            |command_line: &CommandLine| match command::argv(command_line, &spec.shell) {
+                Ok(argv) if command::find_executable(&argv[0], spec).is_some() => None,
+                Ok(argv) => Some(format!("{}: {} not found on PATH", name, argv[0])),
+                Err(err) => Some(format!("{}: {}", name, err)),
+            },
+        )
+        .collect()
+}
@@ -0,0 +1,338 @@
+use crate::config::{CommandLine, ProcessSpec, Shell};
+use crate::rlimit;
+use crate::user;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const DEFAULT_SHELL: &str = "sh";
+
+// First words that only mean something to a shell, so `exit 1` or
+// `FOO=bar cmd` still run the way they did under `sh -c`.
+const SHELL_BUILTINS: &[&str] = &[
+    ".", "alias", "case", "cd", "eval", "exec", "exit", "export", "for", "if", "read", "set",
+    "source", "trap", "ulimit", "umask", "unset", "until", "wait", "while",
+];
+
+// Raising a hard limit can take privileges the process's user lacks, so the
+// limits are set before switching users. std would switch before any
+// pre_exec hook runs, so the switch happens in the hook instead.
+pub fn build(spec: &ProcessSpec) -> io::Result<Command> {
+    if spec.rlimits.is_empty() {
+        return build_with(&spec.command, spec);
+    }
+    rlimit::check(&spec.rlimits)?;
+    let ids = spec.user.as_deref().map(user::resolve).transpose()?;
+    let mut command = build_as(&spec.command, spec, None)?;
+    let rlimits = spec.rlimits.clone();
+    unsafe {
+        command.pre_exec(move || {
+            rlimit::apply(&rlimits)?;
+            if let Some((uid, gid)) = ids {
+                user::switch(uid, gid)?;
+            }
+            Ok(())
+        });
+    }
+    Ok(command)
+}
+
+// Builds an auxiliary command such as a probe with the process's shell,
+// environment, working directory and user.
+pub fn build_with(command_line: &CommandLine, spec: &ProcessSpec) -> io::Result<Command> {
+    let ids = spec.user.as_deref().map(user::resolve).transpose()?;
+    build_as(command_line, spec, ids)
+}
+
+fn build_as(
+    command_line: &CommandLine,
+    spec: &ProcessSpec,
+    ids: Option<(u32, u32)>,
+) -> io::Result<Command> {
+    let argv = argv(command_line, &spec.shell)?;
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    command.envs(&spec.env);
+    if let Some(cwd) = &spec.cwd {
+        command.current_dir(cwd);
+    }
+    if let Some((uid, gid)) = ids {
+        command.uid(uid).gid(gid);
+    }
+    Ok(command)
+}
+
+// Only commands that actually need a shell are wrapped in one, so that the
+// pid spot-init signals is the program itself rather than `sh`.
+pub fn argv(command_line: &CommandLine, shell: &Shell) -> io::Result<Vec<String>> {
+    match (command_line, shell) {
+        (CommandLine::Argv(argv), Shell::Auto) | (CommandLine::Argv(argv), Shell::Never) => {
+            Ok(argv.clone())
+        }
+        (CommandLine::Argv(argv), shell) => {
+            let line = argv.iter().map(|arg| quote(arg)).collect::<Vec<_>>();
+            Ok(shell_argv(shell, &line.join(" ")))
+        }
+        (CommandLine::Shell(line), Shell::Never) => split_words(line, true)
+            .filter(|words| !words.is_empty())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("cannot split `{}` into arguments", line),
+                )
+            }),
+        (CommandLine::Shell(line), Shell::Auto) => match split_words(line, false) {
+            Some(ref words) if !words.is_empty() && !needs_shell(&words[0]) => Ok(words.clone()),
+            _ => Ok(shell_argv(&Shell::Always, line)),
+        },
+        (CommandLine::Shell(line), shell) => Ok(shell_argv(shell, line)),
+    }
+}
+
+// Looks a program up the way exec would, searching the process's own PATH
+// if its env sets one.
+pub fn find_executable(program: &str, spec: &ProcessSpec) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = match &spec.cwd {
+            Some(cwd) => cwd.join(program),
+            None => PathBuf::from(program),
+        };
+        return Some(path).filter(|path| is_executable(path));
+    }
+    let search_path = spec
+        .env
+        .get("PATH")
+        .map(OsString::from)
+        .or_else(|| env::var_os("PATH"))?;
+    env::split_paths(&search_path)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+fn shell_argv(shell: &Shell, line: &str) -> Vec<String> {
+    let shell = match shell {
+        Shell::Custom(path) => path.as_str(),
+        _ => DEFAULT_SHELL,
+    };
+    vec![shell.to_owned(), "-c".to_owned(), line.to_owned()]
+}
+
+fn needs_shell(first_word: &str) -> bool {
+    SHELL_BUILTINS.contains(&first_word) || first_word.contains('=')
+}
+
+// Splits a command line the way sh would for plain words and quotes. Unless
+// `literal` is set, any unquoted shell syntax (pipes, redirects, expansions,
+// globs, ...) makes this return None so the caller falls back to a shell.
+fn split_words(line: &str, literal: bool) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // A newline separates commands, unless nothing follows it.
+            '\n' if !literal && !chars.as_str().trim().is_empty() => return None,
+            ' ' | '\t' | '\n' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            c @ '"' | c @ '\\' | c @ '$' | c @ '`' => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        '$' | '`' if !literal => return None,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.push(chars.next()?);
+            }
+            '|' | '&' | ';' | '<' | '>' | '(' | ')' | '$' | '`' | '*' | '?' | '[' | '#' | '~'
+                if !literal =>
+            {
+                return None
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
+}
+
+fn quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_owned();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Option<Vec<String>> {
+        split_words(line, false)
+    }
+
+    fn literal(line: &str) -> Option<Vec<String>> {
+        split_words(line, true)
+    }
+
+    fn strings(words: &[&str]) -> Option<Vec<String>> {
+        Some(words.iter().map(|word| word.to_string()).collect())
+    }
+
+    #[test]
+    fn plain_words() {
+        assert_eq!(
+            words("nginx -g 'daemon off;'"),
+            strings(&["nginx", "-g", "daemon off;"])
+        );
+        assert_eq!(words("  a\tb  \n"), strings(&["a", "b"]));
+        assert_eq!(words(""), strings(&[]));
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            words("echo 'a \"b\" \\c'"),
+            strings(&["echo", "a \"b\" \\c"])
+        );
+        assert_eq!(
+            words(r#"echo "a 'b' \" \\ \x""#),
+            strings(&["echo", "a 'b' \" \\ \\x"])
+        );
+        assert_eq!(words("echo a'b'\"c\""), strings(&["echo", "abc"]));
+        assert_eq!(words("echo '' \"\""), strings(&["echo", "", ""]));
+        assert_eq!(words("echo a\\ b"), strings(&["echo", "a b"]));
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        assert_eq!(words("echo 'a"), None);
+        assert_eq!(words("echo \"a"), None);
+        assert_eq!(words("echo a\\"), None);
+    }
+
+    #[test]
+    fn shell_syntax_needs_a_shell() {
+        for line in &[
+            "a | b",
+            "a && b",
+            "a; b",
+            "a > out",
+            "a < in",
+            "(a)",
+            "echo $HOME",
+            "echo `date`",
+            "echo \"$HOME\"",
+            "ls *.txt",
+            "ls file?",
+            "ls [ab]",
+            "a # comment",
+            "cd ~",
+            "a\nb",
+        ] {
+            assert_eq!(words(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn quoted_shell_syntax_is_literal() {
+        assert_eq!(words("echo 'a | b $c *'"), strings(&["echo", "a | b $c *"]));
+        assert_eq!(words("echo a\\|b"), strings(&["echo", "a|b"]));
+        assert_eq!(words("echo \"\\$HOME\""), strings(&["echo", "$HOME"]));
+        // Only a newline with something after it separates commands.
+        assert_eq!(words("a b\n  "), strings(&["a", "b"]));
+    }
+
+    #[test]
+    fn literal_splitting_keeps_shell_syntax() {
+        assert_eq!(
+            literal("echo $HOME | *"),
+            strings(&["echo", "$HOME", "|", "*"])
+        );
+        assert_eq!(literal("echo \"$HOME\""), strings(&["echo", "$HOME"]));
+        assert_eq!(literal("a\nb"), strings(&["a", "b"]));
+    }
+
+    #[test]
+    fn argv_only_uses_a_shell_when_needed() {
+        let line = |line: &str| CommandLine::Shell(line.to_owned());
+        assert_eq!(
+            argv(&line("sleep 10"), &Shell::Auto).unwrap(),
+            vec!["sleep", "10"]
+        );
+        assert_eq!(
+            argv(&line("sleep 10 && true"), &Shell::Auto).unwrap(),
+            vec!["sh", "-c", "sleep 10 && true"]
+        );
+        assert_eq!(
+            argv(&line("exit 1"), &Shell::Auto).unwrap(),
+            vec!["sh", "-c", "exit 1"]
+        );
+        assert_eq!(
+            argv(&line("FOO=bar env"), &Shell::Auto).unwrap(),
+            vec!["sh", "-c", "FOO=bar env"]
+        );
+        assert_eq!(argv(&line("a $b"), &Shell::Never).unwrap(), vec!["a", "$b"]);
+        assert!(argv(&line("'open"), &Shell::Never).is_err());
+        assert_eq!(
+            argv(&line("a"), &Shell::Custom("/bin/bash".to_owned())).unwrap(),
+            vec!["/bin/bash", "-c", "a"]
+        );
+    }
+
+    #[test]
+    fn argv_quotes_lists_for_a_shell() {
+        let list = CommandLine::Argv(vec!["echo".to_owned(), "it's here".to_owned()]);
+        assert_eq!(
+            argv(&list, &Shell::Auto).unwrap(),
+            vec!["echo", "it's here"]
+        );
+        assert_eq!(
+            argv(&list, &Shell::Always).unwrap(),
+            vec!["sh", "-c", "echo 'it'\\''s here'"]
+        );
+    }
+}
@@ -0,0 +1,1236 @@
+// The pinned serde_derive 1.0.103 wraps its impls in a `const _: () = {}`
+// block, which current toolchains flag as non-local in every derive below.
+#![allow(non_local_definitions)]
+
+use crate::control;
+use crate::envfile;
+use crate::include::{self, Merger};
+use crate::interpolate;
+use crate::position;
+use crate::procfile;
+use crate::rlimit::Rlimit;
+use crate::schedule::Schedule;
+use crate::signal::{self, Signal};
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(
+        default = "default_stop_timeout",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub stop_timeout: f64,
+    // Non-terminating signals listed here only go to the named processes.
+    #[serde(default, deserialize_with = "deserialize_signal_routes")]
+    pub signals: HashMap<Signal, Vec<String>>,
+    #[serde(default)]
+    pub exit_code_policy: ExitCodePolicy,
+    pub main: Option<String>,
+    #[serde(default)]
+    pub log_color: ColorMode,
+    #[serde(default)]
+    pub log_timestamps: bool,
+    // An empty path disables the control socket.
+    #[serde(default = "default_control_socket")]
+    pub control_socket: PathBuf,
+    // Environment shared by every process, below its own env_file and env.
+    #[serde(default, deserialize_with = "deserialize_paths")]
+    pub env_file: Vec<PathBuf>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    // A signal that reloads the config instead of being forwarded, SIGHUP
+    // unless set to another one or to `false`.
+    #[serde(
+        default = "default_reload_signal",
+        deserialize_with = "deserialize_reload_signal"
+    )]
+    pub reload_signal: Option<Signal>,
+    pub processes: BTreeMap<String, ProcessSpec>,
+}
+
+// `first-failure` exits with the code of the first process that failed, or 0
+// if none did. `main` exits with the code of the process named by `main`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExitCodePolicy {
+    #[default]
+    FirstFailure,
+    Main,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+// How a config file is written. Unless given explicitly it is picked by
+// file name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+    Procfile,
+}
+
+impl Format {
+    pub const NAMES: &'static [&'static str] = &["toml", "yaml", "json", "procfile"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "toml" => Some(Format::Toml),
+            "yaml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            "procfile" => Some(Format::Procfile),
+            _ => None,
+        }
+    }
+
+    // `Procfile` and variants like `Procfile.dev` are Procfiles, `.yaml`,
+    // `.yml` and `.json` files are what they say, anything else is TOML.
+    pub fn detect(path: &Path) -> Self {
+        let file_name = path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        if file_name == "Procfile" || file_name.starts_with("Procfile.") {
+            return Format::Procfile;
+        }
+        match extension.as_deref() {
+            Some("yaml") | Some("yml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+}
+
+impl Config {
+    // A config with every global setting at its default.
+    pub fn from_processes(processes: BTreeMap<String, ProcessSpec>) -> Self {
+        Self {
+            stop_timeout: default_stop_timeout(),
+            signals: HashMap::new(),
+            exit_code_policy: ExitCodePolicy::default(),
+            main: None,
+            log_color: ColorMode::default(),
+            log_timestamps: false,
+            control_socket: default_control_socket(),
+            env_file: Vec::new(),
+            env: HashMap::new(),
+            reload_signal: default_reload_signal(),
+            processes,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // With nothing to supervise there would be nothing to wait for.
+        if self.processes.is_empty() {
+            return Err("no processes are defined".to_owned());
+        }
+        match (&self.exit_code_policy, &self.main) {
+            (ExitCodePolicy::Main, None) => {
+                return Err("exit_code_policy = \"main\" requires `main` to be set".to_owned())
+            }
+            (_, Some(main)) if !self.processes.contains_key(main) => {
+                return Err(format!("main process {} is not defined", main))
+            }
+            _ => {}
+        }
+        for (name, spec) in &self.processes {
+            let job = spec.depends_on.iter().find(|dependency| {
+                self.processes
+                    .get(*dependency)
+                    .is_some_and(|dependency| dependency.schedule.is_some())
+            });
+            if let Some(job) = job {
+                return Err(format!("{} depends on scheduled job {}", name, job));
+            }
+        }
+        match self.reload_signal {
+            Some(signal)
+                if signal::is_terminating(signal) || !signal::FORWARDED.contains(&signal) =>
+            {
+                Err(format!(
+                    "{} cannot be used as reload_signal",
+                    signal::name(signal)
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Everything wrong with a config that parsed, rather than only the first
+    // problem like validate and start_order.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Err(err) = self.validate() {
+            problems.push(err);
+        }
+        for (name, spec) in &self.processes {
+            for dependency in &spec.depends_on {
+                if !self.processes.contains_key(dependency) {
+                    problems.push(format!(
+                        "{} depends on unknown process {}",
+                        name, dependency
+                    ));
+                }
+            }
+        }
+        for (signal, names) in &self.signals {
+            let reason = if signal::is_terminating(*signal) {
+                Some("it stops every process")
+            } else if self.reload_signal == Some(*signal) {
+                Some("it is the reload_signal")
+            } else if !signal::FORWARDED.contains(signal) {
+                Some("it is not forwarded")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                problems.push(format!(
+                    "{} cannot be routed: {}",
+                    signal::name(*signal),
+                    reason
+                ));
+            }
+            for name in names {
+                if !self.processes.contains_key(name) {
+                    problems.push(format!(
+                        "{} is routed to unknown process {}",
+                        signal::name(*signal),
+                        name
+                    ));
+                }
+            }
+        }
+        if let Err(err) = self.start_order() {
+            if !problems.contains(&err) {
+                problems.push(err);
+            }
+        }
+        problems
+    }
+
+    // Loads env files, layers the global environment under each process's
+    // own and expands `${VAR}` references in process settings. Relative
+    // env_file paths are taken from the config file's directory.
+    fn resolve_env(&mut self, config_dir: &Path) -> Result<(), String> {
+        let global_env = layer_env(&HashMap::new(), &self.env_file, &self.env, config_dir, "")?;
+        for (name, spec) in &mut self.processes {
+            let key = format!("processes.{}.", name);
+            spec.env = layer_env(&global_env, &spec.env_file, &spec.env, config_dir, &key)?;
+            spec.expand(&key)?;
+        }
+        Ok(())
+    }
+
+    // Fills in per-process settings that fall back to a global value.
+    fn apply_defaults(&mut self) {
+        for spec in self.processes.values_mut() {
+            spec.stop_timeout.get_or_insert(self.stop_timeout);
+        }
+    }
+
+    // Orders processes so that each one comes after everything it depends on,
+    // breaking ties by name.
+    pub fn start_order(&self) -> Result<Vec<String>, String> {
+        let mut order = Vec::new();
+        let mut visiting = Vec::new();
+        for name in self.processes.keys() {
+            self.visit(name, &mut visiting, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit(
+        &self,
+        name: &str,
+        visiting: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if order.iter().any(|visited| visited == name) {
+            return Ok(());
+        }
+        if let Some(index) = visiting.iter().position(|visited| visited == name) {
+            let mut cycle = visiting[index..].to_vec();
+            cycle.push(name.to_owned());
+            return Err(format!("dependency cycle: {}", cycle.join(" -> ")));
+        }
+
+        visiting.push(name.to_owned());
+        for dependency in &self.processes[name].depends_on {
+            if !self.processes.contains_key(dependency) {
+                return Err(format!(
+                    "{} depends on unknown process {}",
+                    name, dependency
+                ));
+            }
+            self.visit(dependency, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(name.to_owned());
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+// Which processes a signal for a process goes to: only its own pid, its
+// process group, or every descendant found in /proc.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum KillMode {
+    Process,
+    #[default]
+    Group,
+    Tree,
+}
+
+// A oneshot runs to completion instead of for as long as the container
+// does. It counts as ready once it has exited successfully, and that exit
+// does not shut anything down.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProcessKind {
+    #[default]
+    Service,
+    Oneshot,
+}
+
+// Whether a scheduled job is started again while its previous run is still
+// going.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overlap {
+    #[default]
+    Skip,
+    Allow,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(remote = "Self", deny_unknown_fields)]
+pub struct ProcessSpec {
+    #[serde(default, rename = "type")]
+    pub kind: ProcessKind,
+    pub command: CommandLine,
+    #[serde(default)]
+    pub shell: Shell,
+    #[serde(default, deserialize_with = "deserialize_paths")]
+    pub env_file: Vec<PathBuf>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    pub user: Option<String>,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    pub ready: Option<Probe>,
+    #[serde(
+        default = "default_start_timeout",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub start_timeout: f64,
+    #[serde(default, deserialize_with = "deserialize_optional_seconds")]
+    pub stop_timeout: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_signal")]
+    pub stop_signal: Option<Signal>,
+    pub stop_command: Option<CommandLine>,
+    #[serde(default, deserialize_with = "deserialize_signal_map")]
+    pub signal_map: HashMap<Signal, Option<Signal>>,
+    #[serde(default)]
+    pub kill_mode: KillMode,
+    pub log_file: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub log_max_size: Option<u64>,
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    #[serde(default)]
+    pub log_compress: bool,
+    #[serde(default)]
+    pub log_tee: bool,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    #[serde(
+        default = "default_restart_delay",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub restart_delay: f64,
+    #[serde(
+        default = "default_restart_delay_max",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub restart_delay_max: f64,
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: usize,
+    #[serde(
+        default = "default_restart_window",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub restart_window: f64,
+    // Makes the process a job that is run on this schedule.
+    #[serde(default, deserialize_with = "deserialize_schedule")]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub overlap: Overlap,
+    #[serde(default, deserialize_with = "deserialize_rlimits")]
+    pub rlimits: Vec<Rlimit>,
+}
+
+fn default_stop_timeout() -> f64 {
+    10.0
+}
+
+fn default_reload_signal() -> Option<Signal> {
+    Some(libc::SIGHUP)
+}
+
+fn default_control_socket() -> PathBuf {
+    PathBuf::from(control::DEFAULT_SOCKET)
+}
+
+fn default_start_timeout() -> f64 {
+    60.0
+}
+
+fn default_log_max_files() -> usize {
+    5
+}
+
+fn default_restart_delay() -> f64 {
+    1.0
+}
+
+fn default_restart_delay_max() -> f64 {
+    30.0
+}
+
+fn default_max_restarts() -> usize {
+    5
+}
+
+fn default_restart_window() -> f64 {
+    60.0
+}
+
+impl ProcessSpec {
+    // Expands `${VAR}` in everything that names a command, path or address,
+    // looking variables up in the process's environment first.
+    fn expand(&mut self, key: &str) -> Result<(), String> {
+        let env = self.env.clone();
+        let expand = |field: &str, text: &str| {
+            interpolate::expand(text, |name| lookup_env(&env, name))
+                .map_err(|err| format!("{}{}: {}", key, field, err))
+        };
+        let expand_path = |field: &str, path: &Path| match path.to_str() {
+            Some(text) => expand(field, text).map(PathBuf::from),
+            None => Ok(path.to_owned()),
+        };
+        let expand_command_line = |field: &str, command_line: &CommandLine| match command_line {
+            CommandLine::Shell(line) => expand(field, line).map(CommandLine::Shell),
+            CommandLine::Argv(argv) => argv
+                .iter()
+                .map(|arg| expand(field, arg))
+                .collect::<Result<_, _>>()
+                .map(CommandLine::Argv),
+        };
+
+        self.command = expand_command_line("command", &self.command)?;
+        if let Some(stop_command) = &self.stop_command {
+            self.stop_command = Some(expand_command_line("stop_command", stop_command)?);
+        }
+        if let Some(cwd) = &self.cwd {
+            self.cwd = Some(expand_path("cwd", cwd)?);
+        }
+        if let Some(user) = &self.user {
+            self.user = Some(expand("user", user)?);
+        }
+        if let Some(log_file) = &self.log_file {
+            self.log_file = Some(expand_path("log_file", log_file)?);
+        }
+        if let Some(probe) = &mut self.ready {
+            probe.check = match &probe.check {
+                Check::Exec(command_line) => {
+                    Check::Exec(expand_command_line("ready.exec", command_line)?)
+                }
+                Check::Tcp(address) => Check::Tcp(expand("ready.tcp", address)?),
+                Check::Http(url) => Check::Http(expand("ready.http", url)?),
+                Check::File(path) => Check::File(expand_path("ready.file", path)?),
+            };
+        }
+        Ok(())
+    }
+
+    pub fn from_command(command: &str) -> Self {
+        Self {
+            kind: ProcessKind::default(),
+            command: CommandLine::Shell(command.to_owned()),
+            shell: Shell::default(),
+            env_file: Vec::new(),
+            env: HashMap::new(),
+            cwd: None,
+            user: None,
+            depends_on: Vec::new(),
+            ready: None,
+            start_timeout: default_start_timeout(),
+            stop_timeout: None,
+            stop_signal: None,
+            stop_command: None,
+            signal_map: HashMap::new(),
+            kill_mode: KillMode::default(),
+            log_file: None,
+            log_max_size: None,
+            log_max_files: default_log_max_files(),
+            log_compress: false,
+            log_tee: false,
+            restart: RestartPolicy::default(),
+            restart_delay: default_restart_delay(),
+            restart_delay_max: default_restart_delay_max(),
+            max_restarts: default_max_restarts(),
+            restart_window: default_restart_window(),
+            schedule: None,
+            overlap: Overlap::default(),
+            rlimits: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandLine {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandLine::Shell(command) => write!(f, "`{}`", command),
+            CommandLine::Argv(argv) => write!(f, "{:?}", argv),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandLine {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CommandLineVisitor;
+
+        impl<'de> Visitor<'de> for CommandLineVisitor {
+            type Value = CommandLine;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a command string or a non-empty array of arguments")
+            }
+
+            fn visit_str<E: de::Error>(self, command: &str) -> Result<CommandLine, E> {
+                Ok(CommandLine::Shell(command.to_owned()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<CommandLine, A::Error> {
+                let argv = Vec::<String>::deserialize(SeqAccessDeserializer::new(seq))?;
+                if argv.is_empty() {
+                    return Err(de::Error::invalid_length(0, &self));
+                }
+                Ok(CommandLine::Argv(argv))
+            }
+        }
+
+        deserializer.deserialize_any(CommandLineVisitor)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "ProbeTable")]
+pub struct Probe {
+    pub check: Check,
+    pub interval: f64,
+    pub timeout: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    Exec(CommandLine),
+    Tcp(String),
+    Http(String),
+    File(PathBuf),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbeTable {
+    exec: Option<CommandLine>,
+    tcp: Option<TcpAddress>,
+    http: Option<String>,
+    file: Option<PathBuf>,
+    #[serde(
+        default = "default_probe_interval",
+        deserialize_with = "deserialize_seconds"
+    )]
+    interval: f64,
+    #[serde(
+        default = "default_probe_timeout",
+        deserialize_with = "deserialize_seconds"
+    )]
+    timeout: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TcpAddress {
+    Port(u16),
+    Address(String),
+}
+
+fn default_probe_interval() -> f64 {
+    1.0
+}
+
+fn default_probe_timeout() -> f64 {
+    1.0
+}
+
+impl TryFrom<ProbeTable> for Probe {
+    type Error = String;
+
+    fn try_from(table: ProbeTable) -> Result<Self, Self::Error> {
+        let mut checks = Vec::new();
+        if let Some(command) = table.exec {
+            checks.push(Check::Exec(command));
+        }
+        if let Some(address) = table.tcp {
+            checks.push(Check::Tcp(match address {
+                TcpAddress::Port(port) => format!("127.0.0.1:{}", port),
+                TcpAddress::Address(address) => address,
+            }));
+        }
+        if let Some(url) = table.http {
+            if !url.starts_with("http://") {
+                return Err(format!("unsupported url `{}`, expected http://", url));
+            }
+            checks.push(Check::Http(url));
+        }
+        if let Some(path) = table.file {
+            checks.push(Check::File(path));
+        }
+        if checks.len() != 1 {
+            return Err("a ready probe needs exactly one of exec, tcp, http or file".to_owned());
+        }
+
+        Ok(Self {
+            check: checks.remove(0),
+            interval: table.interval,
+            timeout: table.timeout,
+        })
+    }
+}
+
+// Signals are given by name (`SIGQUIT` or `QUIT`) or number.
+struct SignalVisitor;
+
+impl<'de> Visitor<'de> for SignalVisitor {
+    type Value = Signal;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a signal name or number")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Signal, E> {
+        signal::parse(name).ok_or_else(|| E::custom(format!("unknown signal `{}`", name)))
+    }
+
+    fn visit_i64<E: de::Error>(self, number: i64) -> Result<Signal, E> {
+        self.visit_str(&number.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, number: u64) -> Result<Signal, E> {
+        self.visit_str(&number.to_string())
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct SignalName(Signal);
+
+impl<'de> Deserialize<'de> for SignalName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SignalVisitor).map(SignalName)
+    }
+}
+
+// The target of a signal_map entry: a signal, or `false` to drop it. The
+// same goes for reload_signal, where `false` turns reloading by signal off.
+struct ForwardAs(Option<Signal>);
+
+impl<'de> Deserialize<'de> for ForwardAs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ForwardAsVisitor;
+
+        impl<'de> Visitor<'de> for ForwardAsVisitor {
+            type Value = ForwardAs;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a signal name or number, or false")
+            }
+
+            fn visit_bool<E: de::Error>(self, forward: bool) -> Result<ForwardAs, E> {
+                if forward {
+                    return Err(E::invalid_value(de::Unexpected::Bool(forward), &self));
+                }
+                Ok(ForwardAs(None))
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<ForwardAs, E> {
+                SignalVisitor
+                    .visit_str(name)
+                    .map(|signal| ForwardAs(Some(signal)))
+            }
+
+            fn visit_i64<E: de::Error>(self, number: i64) -> Result<ForwardAs, E> {
+                SignalVisitor
+                    .visit_i64(number)
+                    .map(|signal| ForwardAs(Some(signal)))
+            }
+
+            fn visit_u64<E: de::Error>(self, number: u64) -> Result<ForwardAs, E> {
+                SignalVisitor
+                    .visit_u64(number)
+                    .map(|signal| ForwardAs(Some(signal)))
+            }
+        }
+
+        deserializer.deserialize_any(ForwardAsVisitor)
+    }
+}
+
+// Sizes are given in bytes or with a K, M, G or T suffix (powers of 1024).
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last()?.to_ascii_uppercase() {
+        'K' => (&size[..size.len() - 1], 1 << 10),
+        'M' => (&size[..size.len() - 1], 1 << 20),
+        'G' => (&size[..size.len() - 1], 1 << 30),
+        'T' => (&size[..size.len() - 1], 1 << 40),
+        _ => (size, 1),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+// A single path or a list of them.
+fn deserialize_paths<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<PathBuf>, D::Error> {
+    struct PathsVisitor;
+
+    impl<'de> Visitor<'de> for PathsVisitor {
+        type Value = Vec<PathBuf>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a path or a list of paths")
+        }
+
+        fn visit_str<E: de::Error>(self, path: &str) -> Result<Vec<PathBuf>, E> {
+            Ok(vec![PathBuf::from(path)])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Vec<PathBuf>, A::Error> {
+            Vec::<PathBuf>::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(PathsVisitor)
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    struct SizeVisitor;
+
+    impl<'de> Visitor<'de> for SizeVisitor {
+        type Value = u64;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a size in bytes or a string like \"10M\"")
+        }
+
+        fn visit_str<E: de::Error>(self, size: &str) -> Result<u64, E> {
+            parse_size(size).ok_or_else(|| E::invalid_value(de::Unexpected::Str(size), &self))
+        }
+
+        fn visit_i64<E: de::Error>(self, size: i64) -> Result<u64, E> {
+            if size < 0 {
+                return Err(E::invalid_value(de::Unexpected::Signed(size), &self));
+            }
+            Ok(size as u64)
+        }
+
+        fn visit_u64<E: de::Error>(self, size: u64) -> Result<u64, E> {
+            Ok(size)
+        }
+    }
+
+    deserializer.deserialize_any(SizeVisitor).map(Some)
+}
+
+// Durations are given in seconds. Anything a Duration can't hold, or that
+// would overflow a deadline, is rejected here rather than panicking later.
+const MAX_SECONDS: f64 = 1e9;
+
+fn deserialize_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    struct SecondsVisitor;
+
+    impl<'de> Visitor<'de> for SecondsVisitor {
+        type Value = f64;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a number of seconds from 0 to {}", MAX_SECONDS)
+        }
+
+        fn visit_f64<E: de::Error>(self, seconds: f64) -> Result<f64, E> {
+            if !(0.0..=MAX_SECONDS).contains(&seconds) {
+                return Err(E::invalid_value(de::Unexpected::Float(seconds), &self));
+            }
+            Ok(seconds)
+        }
+
+        fn visit_i64<E: de::Error>(self, seconds: i64) -> Result<f64, E> {
+            if seconds < 0 {
+                return Err(E::invalid_value(de::Unexpected::Signed(seconds), &self));
+            }
+            self.visit_u64(seconds as u64)
+        }
+
+        fn visit_u64<E: de::Error>(self, seconds: u64) -> Result<f64, E> {
+            if seconds as f64 > MAX_SECONDS {
+                return Err(E::invalid_value(de::Unexpected::Unsigned(seconds), &self));
+            }
+            Ok(seconds as f64)
+        }
+    }
+
+    deserializer.deserialize_any(SecondsVisitor)
+}
+
+fn deserialize_optional_seconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    deserialize_seconds(deserializer).map(Some)
+}
+
+fn deserialize_signal<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Signal>, D::Error> {
+    deserializer.deserialize_any(SignalVisitor).map(Some)
+}
+
+fn deserialize_schedule<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Schedule>, D::Error> {
+    let expression = String::deserialize(deserializer)?;
+    Schedule::parse(&expression)
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+// `rlimits = { nofile = 65536, as = "2G" }`, set in name order.
+fn deserialize_rlimits<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Rlimit>, D::Error> {
+    let rlimits = BTreeMap::<String, LimitValue>::deserialize(deserializer)?;
+    rlimits
+        .iter()
+        .map(|(name, LimitValue(value))| Rlimit::parse(name, value).map_err(de::Error::custom))
+        .collect()
+}
+
+// A limit as written, a number or a string, for Rlimit::parse to interpret.
+struct LimitValue(String);
+
+impl<'de> Deserialize<'de> for LimitValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LimitVisitor;
+
+        impl<'de> Visitor<'de> for LimitVisitor {
+            type Value = LimitValue;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number, a size like \"2G\" or \"unlimited\"")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<LimitValue, E> {
+                Ok(LimitValue(value.to_owned()))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<LimitValue, E> {
+                if value < 0 {
+                    return Err(E::invalid_value(de::Unexpected::Signed(value), &self));
+                }
+                Ok(LimitValue(value.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<LimitValue, E> {
+                Ok(LimitValue(value.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(LimitVisitor)
+    }
+}
+
+fn deserialize_reload_signal<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Signal>, D::Error> {
+    ForwardAs::deserialize(deserializer).map(|ForwardAs(signal)| signal)
+}
+
+fn deserialize_signal_routes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Signal, Vec<String>>, D::Error> {
+    let routes = HashMap::<SignalName, Vec<String>>::deserialize(deserializer)?;
+    Ok(routes
+        .into_iter()
+        .map(|(SignalName(signal), names)| (signal, names))
+        .collect())
+}
+
+fn deserialize_signal_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Signal, Option<Signal>>, D::Error> {
+    let signal_map = HashMap::<SignalName, ForwardAs>::deserialize(deserializer)?;
+    Ok(signal_map
+        .into_iter()
+        .map(|(SignalName(signal), ForwardAs(forward_as))| (signal, forward_as))
+        .collect())
+}
+
+// `shell = true` runs the command through `sh -c`, `shell = "/bin/bash"`
+// through that shell and `shell = false` execs it directly. When unset, string
+// commands only go through a shell if they use shell syntax.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Shell {
+    #[default]
+    Auto,
+    Never,
+    Always,
+    Custom(String),
+}
+
+impl<'de> Deserialize<'de> for Shell {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ShellVisitor;
+
+        impl<'de> Visitor<'de> for ShellVisitor {
+            type Value = Shell;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a boolean or the path to a shell")
+            }
+
+            fn visit_bool<E: de::Error>(self, shell: bool) -> Result<Shell, E> {
+                Ok(if shell { Shell::Always } else { Shell::Never })
+            }
+
+            fn visit_str<E: de::Error>(self, shell: &str) -> Result<Shell, E> {
+                Ok(Shell::Custom(shell.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_any(ShellVisitor)
+    }
+}
+
+// A process is either a plain command string or a table of settings.
+impl<'de> Deserialize<'de> for ProcessSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpecVisitor;
+
+        impl<'de> Visitor<'de> for SpecVisitor {
+            type Value = ProcessSpec;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a command string or a process table")
+            }
+
+            fn visit_str<E: de::Error>(self, command: &str) -> Result<ProcessSpec, E> {
+                Ok(ProcessSpec::from_command(command))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ProcessSpec, A::Error> {
+                let spec = ProcessSpec::deserialize(MapAccessDeserializer::new(map))?;
+                if spec.schedule.is_some() {
+                    let conflict = if spec.kind == ProcessKind::Oneshot {
+                        Some("type = \"oneshot\"")
+                    } else if spec.ready.is_some() {
+                        Some("a ready probe")
+                    } else if spec.restart != RestartPolicy::Never {
+                        Some("a restart policy")
+                    } else if !spec.depends_on.is_empty() {
+                        Some("depends_on")
+                    } else {
+                        None
+                    };
+                    if let Some(conflict) = conflict {
+                        return Err(de::Error::custom(format!(
+                            "a scheduled job cannot have {}",
+                            conflict
+                        )));
+                    }
+                }
+                if spec.kind == ProcessKind::Oneshot {
+                    if spec.ready.is_some() {
+                        return Err(de::Error::custom(
+                            "a oneshot is ready once it has exited, it cannot have a ready probe",
+                        ));
+                    }
+                    if spec.restart == RestartPolicy::Always {
+                        return Err(de::Error::custom(
+                            "a oneshot cannot have restart = \"always\"",
+                        ));
+                    }
+                }
+                Ok(spec)
+            }
+        }
+
+        deserializer.deserialize_any(SpecVisitor)
+    }
+}
+
+// Parse errors name the offending key along with its line and column. A
+// directory, or a config with `include`, is merged from several files as
+// described in the include module.
+pub fn load_config(config_path: &str, format: Option<Format>) -> Result<Config, String> {
+    let config_path = Path::new(config_path);
+    if config_path.is_dir() {
+        if format.is_some() {
+            return Err(format!(
+                "{} is a directory, its files are read by extension",
+                config_path.display()
+            ));
+        }
+        let mut merger = Merger::new();
+        merger.add_dir(config_path)?;
+        return finish_loading(merger.finish()?, Path::new(""));
+    }
+
+    let contents = fs::read_to_string(config_path)
+        .map_err(|err| format!("failed to read {}: {}", config_path.display(), err))?;
+    let format = format.unwrap_or_else(|| Format::detect(config_path));
+    // Procfile commands are left to the shell, like other Procfile runners do.
+    if format == Format::Procfile {
+        let mut config = procfile::parse(config_path, &contents)?;
+        config.apply_defaults();
+        return Ok(config);
+    }
+    let (value, positions) = include::parse(format, &contents)?;
+    if value.get("include").is_some() {
+        let mut merger = Merger::new();
+        merger.add(config_path, value, &positions)?;
+        return finish_loading(merger.finish()?, Path::new(""));
+    }
+    let config = match format {
+        // Deserializing from the text keeps line numbers in errors.
+        Format::Toml => toml::from_str(&contents).map_err(|err| err.to_string())?,
+        _ => value
+            .try_into()
+            .map_err(|err| position::locate(err, &positions))?,
+    };
+    finish_loading(
+        config,
+        config_path.parent().unwrap_or_else(|| Path::new("")),
+    )
+}
+
+fn finish_loading(mut config: Config, config_dir: &Path) -> Result<Config, String> {
+    config.resolve_env(config_dir)?;
+    config.apply_defaults();
+    Ok(config)
+}
+
+// Stacks env files and then an env table on top of `base`. Values in the
+// table may refer to anything below them as well as to spot-init's own
+// environment.
+fn layer_env(
+    base: &HashMap<String, String>,
+    env_files: &[PathBuf],
+    env: &HashMap<String, String>,
+    config_dir: &Path,
+    key: &str,
+) -> Result<HashMap<String, String>, String> {
+    let mut layered = base.clone();
+    for env_file in env_files {
+        let path = config_dir.join(env_file);
+        let contents = fs::read_to_string(&path).map_err(|err| {
+            format!(
+                "{}env_file: failed to read {}: {}",
+                key,
+                path.display(),
+                err
+            )
+        })?;
+        layered.extend(
+            envfile::parse(&contents).map_err(|err| format!("{}: {}", path.display(), err))?,
+        );
+    }
+
+    let mut expanded = HashMap::new();
+    for (name, value) in env {
+        let value = interpolate::expand(value, |name| lookup_env(&layered, name))
+            .map_err(|err| format!("{}env.{}: {}", key, name, err))?;
+        expanded.insert(name.clone(), value);
+    }
+    layered.extend(expanded);
+    Ok(layered)
+}
+
+fn lookup_env(env: &HashMap<String, String>, name: &str) -> Option<String> {
+    env.get(name).cloned().or_else(|| env::var(name).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(processes: &[(&str, &[&str])]) -> Config {
+        let processes = processes
+            .iter()
+            .map(|(name, depends_on)| {
+                let mut spec = ProcessSpec::from_command("true");
+                spec.depends_on = depends_on.iter().map(|&name| name.to_owned()).collect();
+                (name.to_string(), spec)
+            })
+            .collect();
+        Config::from_processes(processes)
+    }
+
+    fn spec(toml: &str) -> Result<ProcessSpec, String> {
+        toml::from_str::<BTreeMap<String, ProcessSpec>>(toml)
+            .map(|mut specs| specs.remove("p").unwrap())
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn starts_dependencies_first() {
+        let config = config(&[("app", &["db", "cache"]), ("cache", &["db"]), ("db", &[])]);
+        assert_eq!(config.start_order().unwrap(), ["db", "cache", "app"]);
+    }
+
+    #[test]
+    fn breaks_ties_by_name() {
+        let config = config(&[("c", &[]), ("b", &[]), ("a", &["c"])]);
+        assert_eq!(config.start_order().unwrap(), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn rejects_a_dependency_cycle() {
+        let config = config(&[("a", &["b"]), ("b", &["a"])]);
+        assert_eq!(
+            config.start_order().unwrap_err(),
+            "dependency cycle: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn rejects_an_unknown_dependency() {
+        let config = config(&[("a", &["b"])]);
+        assert_eq!(
+            config.start_order().unwrap_err(),
+            "a depends on unknown process b"
+        );
+    }
+
+    #[test]
+    fn reads_a_string_as_a_command() {
+        assert_eq!(
+            spec("p = \"nginx -g 'daemon off;'\"").unwrap(),
+            ProcessSpec::from_command("nginx -g 'daemon off;'")
+        );
+    }
+
+    #[test]
+    fn reads_a_table() {
+        let spec = spec("[p]\ncommand = \"redis-server\"\nrestart = \"always\"\n").unwrap();
+        assert_eq!(spec.command, CommandLine::Shell("redis-server".to_owned()));
+        assert_eq!(spec.restart, RestartPolicy::Always);
+        assert_eq!(spec.kind, ProcessKind::Service);
+    }
+
+    #[test]
+    fn reads_an_argv_command() {
+        let spec = spec("[p]\ncommand = [\"sh\", \"-c\", \"echo $0\"]\n").unwrap();
+        assert_eq!(
+            spec.command,
+            CommandLine::Argv(vec!["sh".to_owned(), "-c".to_owned(), "echo $0".to_owned()])
+        );
+    }
+
+    #[test]
+    fn rejects_an_empty_argv() {
+        let err = spec("[p]\ncommand = []\n").unwrap_err();
+        assert!(
+            err.contains("invalid length 0, expected a command string or a non-empty array"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_settings_a_scheduled_job_cannot_have() {
+        let conflicts = [
+            ("type = \"oneshot\"", "type = \"oneshot\""),
+            ("ready = { tcp = \":80\" }", "a ready probe"),
+            ("restart = \"on-failure\"", "a restart policy"),
+            ("depends_on = [\"db\"]", "depends_on"),
+        ];
+        for (setting, conflict) in conflicts {
+            let toml = format!(
+                "[p]\ncommand = \"backup\"\nschedule = \"@daily\"\n{}\n",
+                setting
+            );
+            let err = spec(&toml).unwrap_err();
+            assert!(
+                err.contains(&format!("a scheduled job cannot have {}", conflict)),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_settings_a_oneshot_cannot_have() {
+        let err =
+            spec("[p]\ncommand = \"migrate\"\ntype = \"oneshot\"\nready = { tcp = \":80\" }\n")
+                .unwrap_err();
+        assert!(
+            err.contains("a oneshot is ready once it has exited"),
+            "{}",
+            err
+        );
+        let err = spec("[p]\ncommand = \"migrate\"\ntype = \"oneshot\"\nrestart = \"always\"\n")
+            .unwrap_err();
+        assert!(
+            err.contains("a oneshot cannot have restart = \"always\""),
+            "{}",
+            err
+        );
+        let spec =
+            spec("[p]\ncommand = \"migrate\"\ntype = \"oneshot\"\nrestart = \"on-failure\"\n")
+                .unwrap();
+        assert_eq!(spec.kind, ProcessKind::Oneshot);
+    }
+}
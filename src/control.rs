@@ -0,0 +1,149 @@
+use crate::event::Event;
+use crate::signal;
+use crate::supervisor::Supervisor;
+use std::fs;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+pub const DEFAULT_SOCKET: &str = "/run/spot-init.sock";
+
+// A request is a single line of words, e.g. `restart web`. The reply is
+// everything written back before the connection is closed, and starts with
+// `error: ` if the request failed.
+pub fn listen(path: &Path, supervisor: Arc<Supervisor>) -> io::Result<()> {
+    // A socket left behind by an earlier run would make bind fail, but one
+    // that still answers belongs to another spot-init and is left alone.
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another process is already listening on it",
+        ));
+    }
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let supervisor = supervisor.clone();
+            thread::spawn(move || handle(stream, &supervisor));
+        }
+    });
+    Ok(())
+}
+
+pub fn request(path: &Path, request: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.write_all(format!("{}\n", request).as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+fn handle(mut stream: UnixStream, supervisor: &Supervisor) {
+    let mut request = String::new();
+    if BufReader::new(&stream).read_line(&mut request).is_err() {
+        return;
+    }
+    let request = request.trim();
+    Event::new("control")
+        .string("request", request)
+        .log(format_args!("control request: {}", request));
+    let reply = execute(request, supervisor).unwrap_or_else(|err| format!("error: {}\n", err));
+    let _ = stream.write_all(reply.as_bytes());
+}
+
+// Stopping, restarting and reloading block until the old processes have
+// exited, so the reply tells the caller that they are gone. Scheduled jobs
+// run on their own and can only be signaled.
+fn execute(request: &str, supervisor: &Supervisor) -> Result<String, String> {
+    let processes = supervisor.processes();
+    let jobs = supervisor.jobs();
+    let find_job = |name: &str| jobs.iter().find(|job| job.name() == name);
+    let find = |name: &str| {
+        processes
+            .iter()
+            .find(|process| process.name() == name)
+            .ok_or_else(|| match find_job(name) {
+                Some(_) => format!("{} is a scheduled job, it can only be signaled", name),
+                None => format!("unknown process {}", name),
+            })
+    };
+
+    match request.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["status"] => Ok(status(supervisor)),
+        ["reload"] => supervisor.reload(),
+        ["start", name] => {
+            find(name)?.resume()?;
+            Ok(format!("started {}\n", name))
+        }
+        ["stop", name] => {
+            let process = find(name)?;
+            process.hold()?;
+            process.wait();
+            Ok(format!("stopped {}\n", name))
+        }
+        ["restart", name] => {
+            let process = find(name)?;
+            if process.hold().is_ok() {
+                process.wait();
+            }
+            process.resume()?;
+            Ok(format!("restarted {}\n", name))
+        }
+        ["signal", name, signal_name] => {
+            let signal = signal::parse(signal_name)
+                .ok_or_else(|| format!("unknown signal {}", signal_name))?;
+            let is_running = match find_job(name) {
+                Some(job) => job.send_signal(signal),
+                None => find(name)?.send_signal(signal),
+            };
+            if !is_running {
+                return Err(format!("{} is not running", name));
+            }
+            Ok(format!("sent {} to {}\n", signal::name(signal), name))
+        }
+        _ => Err(format!("invalid request: {}", request)),
+    }
+}
+
+// Processes in start order, then scheduled jobs.
+fn status(supervisor: &Supervisor) -> String {
+    let statuses = supervisor
+        .processes()
+        .iter()
+        .map(|process| (process.name().to_owned(), process.status()))
+        .chain(
+            supervisor
+                .jobs()
+                .iter()
+                .map(|job| (job.name().to_owned(), job.status())),
+        )
+        .collect::<Vec<_>>();
+    let name_width = statuses
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    statuses
+        .iter()
+        .map(|(name, (status, pid))| {
+            let pid = pid.map_or_else(|| "-".to_owned(), |pid| pid.to_string());
+            format!(
+                "{:<width$}  {:<10} {}\n",
+                name,
+                status,
+                pid,
+                width = name_width
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+// Parses a dotenv file: `KEY=value` lines, optionally prefixed with
+// `export`, with blank lines and `#` comments ignored. Values may be single
+// quoted (taken literally) or double quoted (with \n, \t, \" and \\ escapes).
+pub fn parse(contents: &str) -> Result<HashMap<String, String>, String> {
+    let mut env = HashMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=value", index + 1))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!(
+                "line {}: invalid variable name `{}`",
+                index + 1,
+                key
+            ));
+        }
+        let value = parse_value(value.trim())
+            .ok_or_else(|| format!("line {}: unterminated quote in {}", index + 1, key))?;
+        env.insert(key.to_owned(), value);
+    }
+    Ok(env)
+}
+
+fn parse_value(value: &str) -> Option<String> {
+    if let Some(quoted) = value.strip_prefix('\'') {
+        return quoted.find('\'').map(|end| quoted[..end].to_owned());
+    }
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut parsed = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Some(parsed),
+                '\\' => match chars.next()? {
+                    'n' => parsed.push('\n'),
+                    't' => parsed.push('\t'),
+                    c => parsed.push(c),
+                },
+                c => parsed.push(c),
+            }
+        }
+        return None;
+    }
+    // Unquoted values end at a comment.
+    let end = value.find(" #").unwrap_or(value.len());
+    Some(value[..end].trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(contents: &str, key: &str) -> Option<String> {
+        parse(contents).unwrap().get(key).cloned()
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let env = parse("# a comment\n\n  \nA=1\n  # indented\nB=2\n").unwrap();
+        assert_eq!(env.len(), 2);
+        assert_eq!(env["A"], "1");
+        assert_eq!(env["B"], "2");
+    }
+
+    #[test]
+    fn strips_export() {
+        assert_eq!(get("export PORT=8080", "PORT").as_deref(), Some("8080"));
+    }
+
+    #[test]
+    fn unquotes_values() {
+        assert_eq!(get("A='$HOME \\n'", "A").as_deref(), Some("$HOME \\n"));
+        assert_eq!(
+            get("A=\"one\\ntwo\\t\\\"three\\\\\"", "A").as_deref(),
+            Some("one\ntwo\t\"three\\")
+        );
+        assert_eq!(get("A=\"a # b\" # comment", "A").as_deref(), Some("a # b"));
+    }
+
+    #[test]
+    fn ends_unquoted_values_at_a_comment() {
+        assert_eq!(get("A=value # comment", "A").as_deref(), Some("value"));
+        assert_eq!(
+            get("A=value#not-a-comment", "A").as_deref(),
+            Some("value#not-a-comment")
+        );
+        assert_eq!(get("A=", "A").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert_eq!(parse("A=1\nB").unwrap_err(), "line 2: expected KEY=value");
+        assert_eq!(
+            parse("MY-VAR=1").unwrap_err(),
+            "line 1: invalid variable name `MY-VAR`"
+        );
+        assert_eq!(parse("=1").unwrap_err(), "line 1: invalid variable name ``");
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        assert_eq!(
+            parse("A='value").unwrap_err(),
+            "line 1: unterminated quote in A"
+        );
+        assert_eq!(
+            parse("\nA=\"value\\\"").unwrap_err(),
+            "line 2: unterminated quote in A"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::config::CommandLine;
+use std::fmt;
+use std::io;
+
+// Exit codes for spot-init's own failures. Config and runtime errors follow
+// sysexits.h, a process that cannot be spawned exits the way a shell does
+// for a command it could not run.
+const CONFIG_EXIT_CODE: i32 = 78;
+const SPAWN_EXIT_CODE: i32 = 127;
+const RUNTIME_EXIT_CODE: i32 = 70;
+
+#[derive(Debug)]
+pub enum Error {
+    // The config file could not be read, parsed or validated.
+    Config(String),
+    // A process could not be started.
+    Spawn {
+        name: String,
+        command: CommandLine,
+        source: io::Error,
+    },
+    // Anything else that keeps spot-init from supervising its processes.
+    Runtime(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => CONFIG_EXIT_CODE,
+            Error::Spawn { .. } => SPAWN_EXIT_CODE,
+            Error::Runtime(_) => RUNTIME_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(message) => write!(f, "invalid config: {}", message),
+            Error::Spawn {
+                name,
+                command,
+                source,
+            } => write!(f, "failed to execute {}: {}: {}", name, command, source),
+            Error::Runtime(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spawn { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
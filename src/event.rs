@@ -0,0 +1,112 @@
+use crate::output;
+use crate::signal::{self, Signal};
+use std::fmt::{self, Write as _};
+use std::io::{self, Write as _};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static USE_JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn use_json(use_json: bool) {
+    USE_JSON.store(use_json, Ordering::Relaxed);
+}
+
+#[derive(Debug)]
+enum Value {
+    String(String),
+    Number(i64),
+}
+
+// A supervisor event. In text mode only the message is printed, in json mode
+// one object per line with the event name, the fields and the message.
+#[derive(Debug)]
+pub struct Event {
+    event: &'static str,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Event {
+    pub fn new(event: &'static str) -> Self {
+        Self {
+            event,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn process(self, name: &str) -> Self {
+        self.string("process", name)
+    }
+
+    pub fn pid(self, pid: u32) -> Self {
+        self.number("pid", i64::from(pid))
+    }
+
+    pub fn signal(self, signal: Signal) -> Self {
+        self.string("signal", signal::name(signal))
+    }
+
+    pub fn exit_status(self, exit_status: ExitStatus) -> Self {
+        match (exit_status.code(), exit_status.signal()) {
+            (Some(code), _) => self.number("exit_code", i64::from(code)),
+            (None, Some(signal)) => self.signal(signal),
+            (None, None) => self,
+        }
+    }
+
+    pub fn string(mut self, key: &'static str, value: &str) -> Self {
+        self.fields.push((key, Value::String(value.to_owned())));
+        self
+    }
+
+    pub fn number(mut self, key: &'static str, value: i64) -> Self {
+        self.fields.push((key, Value::Number(value)));
+        self
+    }
+
+    pub fn log(self, message: fmt::Arguments) {
+        if !USE_JSON.load(Ordering::Relaxed) {
+            print_line(&message.to_string());
+            return;
+        }
+
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "{{\"time\":\"{}\",\"event\":\"{}\"",
+            output::timestamp(),
+            self.event
+        );
+        for (key, value) in &self.fields {
+            let _ = match value {
+                Value::String(value) => write!(line, ",\"{}\":\"{}\"", key, escape(value)),
+                Value::Number(value) => write!(line, ",\"{}\":{}", key, value),
+            };
+        }
+        let _ = write!(line, ",\"message\":\"{}\"}}", escape(&message.to_string()));
+        print_line(&line);
+    }
+}
+
+// Like output::write_line, a closed stdout must not take the supervisor down.
+fn print_line(line: &str) {
+    let _ = writeln!(io::stdout().lock(), "{}", line);
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,484 @@
+use crate::config::{Config, Format};
+use crate::json;
+use crate::position::{self, Positions};
+use crate::yaml;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+// Configs spread over several files, either named by `include` or found in a
+// directory, are merged as value trees before they are deserialized:
+//
+// - Files a config includes are merged before the config itself, in the
+//   order their patterns are listed and by name within a pattern, so the
+//   including file overrides what it includes. A directory is merged in name
+//   order.
+// - A later global setting replaces an earlier one, except that `env` and
+//   `signals` are merged key by key and `env_file` lists are appended.
+// - A process may only be defined once across all files.
+// - `include` patterns and `env_file` paths are relative to the file they
+//   are written in.
+pub struct Merger {
+    merged: Table,
+    // Which file each process came from, for duplicate errors.
+    sources: HashMap<String, PathBuf>,
+    loaded: Vec<PathBuf>,
+}
+
+impl Merger {
+    pub fn new() -> Self {
+        Self {
+            merged: Table::new(),
+            sources: HashMap::new(),
+            loaded: Vec::new(),
+        }
+    }
+
+    // Reads every TOML, YAML and JSON file in a directory.
+    pub fn add_dir(&mut self, dir: &Path) -> Result<(), String> {
+        let entries = fs::read_dir(dir)
+            .map_err(|err| format!("failed to read {}: {}", dir.display(), err))?;
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|err| format!("failed to read {}: {}", dir.display(), err))?
+                .path();
+            if path.is_file() && is_config_file(&path) {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Err(format!("no config files in {}", dir.display()));
+        }
+        paths.sort();
+        for path in paths {
+            self.add_file(&path, Format::detect(&path))?;
+        }
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, path: &Path, format: Format) -> Result<(), String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let (value, positions) =
+            parse(format, &contents).map_err(|err| format!("{}: {}", path.display(), err))?;
+        self.add(path, value, &positions)
+    }
+
+    // Merges an already parsed file, after the files it includes.
+    pub fn add(&mut self, path: &Path, value: Value, positions: &Positions) -> Result<(), String> {
+        let canonical = fs::canonicalize(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        if self.loaded.contains(&canonical) {
+            return Err(format!("{} is included more than once", path.display()));
+        }
+        self.loaded.push(canonical);
+
+        let in_file = |err: String| format!("{}: {}", path.display(), err);
+        let mut table = match value {
+            Value::Table(table) => table,
+            _ => return Err(in_file("expected a table".to_owned())),
+        };
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let patterns = match table.remove("include") {
+            Some(include) => strings(include).ok_or_else(|| {
+                in_file("include: expected a pattern or a list of patterns".to_owned())
+            })?,
+            None => Vec::new(),
+        };
+        relocate_env_files(&mut table, dir);
+        check(&table, positions).map_err(in_file)?;
+
+        for pattern in patterns {
+            for included in expand(dir, &pattern).map_err(in_file)? {
+                match Format::detect(&included) {
+                    Format::Procfile => {
+                        return Err(in_file(format!(
+                            "cannot include Procfile {}",
+                            included.display()
+                        )))
+                    }
+                    format => self.add_file(&included, format)?,
+                }
+            }
+        }
+        self.merge(path, table)
+    }
+
+    pub fn finish(self) -> Result<Config, String> {
+        Value::Table(self.merged)
+            .try_into()
+            .map_err(|err| err.to_string())
+    }
+
+    fn merge(&mut self, path: &Path, table: Table) -> Result<(), String> {
+        for (key, value) in table {
+            match (key.as_str(), value, self.merged.get_mut(&key)) {
+                ("processes", Value::Table(processes), _) => {
+                    for (name, spec) in processes {
+                        if let Some(source) = self.sources.get(&name) {
+                            return Err(format!(
+                                "process {} is defined in both {} and {}",
+                                name,
+                                source.display(),
+                                path.display()
+                            ));
+                        }
+                        self.sources.insert(name.clone(), path.to_owned());
+                        self.merged
+                            .entry("processes")
+                            .or_insert_with(|| Value::Table(Table::new()))
+                            .as_table_mut()
+                            .expect("processes is a table")
+                            .insert(name, spec);
+                    }
+                }
+                ("env", Value::Table(table), Some(Value::Table(merged)))
+                | ("signals", Value::Table(table), Some(Value::Table(merged))) => {
+                    merged.extend(table)
+                }
+                ("env_file", Value::Array(env_files), Some(Value::Array(merged))) => {
+                    merged.extend(env_files)
+                }
+                (_, value, _) => {
+                    self.merged.insert(key, value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Along with the value come the positions of keys, for formats whose errors
+// would otherwise have none. TOML errors already carry them.
+pub fn parse(format: Format, contents: &str) -> Result<(Value, Positions), String> {
+    match format {
+        Format::Toml => toml::from_str(contents)
+            .map(|value| (value, Positions::new()))
+            .map_err(|err| err.to_string()),
+        Format::Yaml => yaml::parse(contents),
+        Format::Json => json::parse(contents),
+        Format::Procfile => Err("a Procfile cannot be merged with other configs".to_owned()),
+    }
+}
+
+fn is_config_file(path: &Path) -> bool {
+    let is_hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    !is_hidden
+        && matches!(
+            extension.as_deref(),
+            Some("toml") | Some("yaml") | Some("yml") | Some("json")
+        )
+}
+
+// Deserializes a single file on its own, so that mistakes in it are reported
+// against that file rather than the merged config.
+fn check(table: &Table, positions: &Positions) -> Result<(), String> {
+    let mut table = table.clone();
+    table
+        .entry("processes")
+        .or_insert_with(|| Value::Table(Table::new()));
+    Value::Table(table)
+        .try_into::<Config>()
+        .map(drop)
+        .map_err(|err| position::locate(err, positions))
+}
+
+// Rewrites relative env_file paths, global and per process, to be relative
+// to the directory of the file they came from.
+fn relocate_env_files(table: &mut Table, dir: &Path) {
+    let relocate = |table: &mut Table| {
+        let env_files = table.get("env_file").cloned().and_then(strings);
+        if let Some(env_files) = env_files {
+            let env_files = env_files
+                .iter()
+                .map(|env_file| Value::String(dir.join(env_file).to_string_lossy().into_owned()))
+                .collect();
+            table.insert("env_file".to_owned(), Value::Array(env_files));
+        }
+    };
+    relocate(table);
+    if let Some(Value::Table(processes)) = table.get_mut("processes") {
+        for (_, spec) in processes.iter_mut() {
+            if let Value::Table(spec) = spec {
+                relocate(spec);
+            }
+        }
+    }
+}
+
+// A string or a list of strings.
+fn strings(value: Value) -> Option<Vec<String>> {
+    match value {
+        Value::String(string) => Some(vec![string]),
+        Value::Array(array) => array
+            .into_iter()
+            .map(|value| match value {
+                Value::String(string) => Some(string),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+// Finds the files an include pattern names. `*` and `?` may be used in the
+// file name; a pattern without them must name an existing file.
+fn expand(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = dir.join(pattern);
+    let file_pattern = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    if parent.to_string_lossy().contains(['*', '?']) {
+        return Err(format!(
+            "include {}: wildcards are only allowed in file names",
+            pattern
+        ));
+    }
+    if !file_pattern.contains(['*', '?']) {
+        return match path.is_file() {
+            true => Ok(vec![path]),
+            false => Err(format!("include {}: no such file", pattern)),
+        };
+    }
+
+    let search_dir = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    let entries = match fs::read_dir(search_dir) {
+        Ok(entries) => entries,
+        // An empty conf.d may as well not exist.
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("failed to read {}: {}", parent.display(), err)),
+    };
+    let pattern: Vec<char> = file_pattern.chars().collect();
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| format!("failed to read {}: {}", parent.display(), err))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let name: Vec<char> = name.chars().collect();
+        // Like a shell, wildcards don't match hidden files.
+        if name.first() == Some(&'.') && pattern.first() != Some(&'.') {
+            continue;
+        }
+        if matches_pattern(&pattern, &name) && entry.path().is_file() {
+            paths.push(parent.join(entry.file_name()));
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn matches_pattern(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // A fresh directory per test, so tests can run in parallel.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("spot-init-include-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn load(dir: &Path, files: &[(&str, &str)]) -> Result<Config, String> {
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let mut merger = Merger::new();
+        merger.add_file(&dir.join(files[0].0), Format::Toml)?;
+        merger.finish()
+    }
+
+    #[test]
+    fn lets_the_including_file_override_scalars() {
+        let dir = scratch_dir("scalars");
+        let config = load(
+            &dir,
+            &[
+                ("main.toml", "include = \"base.toml\"\nstop_timeout = 5\n"),
+                (
+                    "base.toml",
+                    "stop_timeout = 30\n[processes]\na = \"true\"\n",
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.stop_timeout, 5.0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn merges_env_and_signals_key_by_key() {
+        let dir = scratch_dir("tables");
+        let config = load(
+            &dir,
+            &[
+                (
+                    "main.toml",
+                    "include = \"base.toml\"\n\
+                     [env]\nB = \"main\"\nC = \"main\"\n\
+                     [signals]\nSIGUSR2 = [\"a\"]\n",
+                ),
+                (
+                    "base.toml",
+                    "[env]\nA = \"base\"\nB = \"base\"\n\
+                     [signals]\nSIGUSR1 = [\"a\"]\n\
+                     [processes]\na = \"true\"\n",
+                ),
+            ],
+        )
+        .unwrap();
+        let env = |name: &str| config.env.get(name).map(String::as_str);
+        assert_eq!(env("A"), Some("base"));
+        assert_eq!(env("B"), Some("main"));
+        assert_eq!(env("C"), Some("main"));
+        assert_eq!(
+            config.signals.get(&libc::SIGUSR1),
+            Some(&vec!["a".to_owned()])
+        );
+        assert_eq!(
+            config.signals.get(&libc::SIGUSR2),
+            Some(&vec!["a".to_owned()])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn appends_env_files_relative_to_their_file() {
+        let dir = scratch_dir("env-files");
+        fs::create_dir(dir.join("conf.d")).unwrap();
+        let config = load(
+            &dir,
+            &[
+                (
+                    "main.toml",
+                    "include = \"conf.d/base.toml\"\nenv_file = \"main.env\"\n",
+                ),
+                (
+                    "conf.d/base.toml",
+                    "env_file = \"base.env\"\n[processes]\na = \"true\"\n",
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            config.env_file,
+            [dir.join("conf.d/base.env"), dir.join("main.env")]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_process_defined_twice() {
+        let dir = scratch_dir("duplicate");
+        let err = load(
+            &dir,
+            &[
+                (
+                    "main.toml",
+                    "include = \"base.toml\"\n[processes]\na = \"true\"\n",
+                ),
+                ("base.toml", "[processes]\na = \"false\"\n"),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "process a is defined in both {} and {}",
+                dir.join("base.toml").display(),
+                dir.join("main.toml").display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_file_included_twice() {
+        let dir = scratch_dir("twice");
+        let err = load(
+            &dir,
+            &[
+                ("main.toml", "include = [\"base.toml\", \"*.toml\"]\n"),
+                ("base.toml", "[processes]\na = \"true\"\n"),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "{} is included more than once",
+                dir.join("base.toml").display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn expands_wildcards_except_over_hidden_files() {
+        let dir = scratch_dir("wildcards");
+        for name in ["a.toml", "b.toml", "b.yaml", ".hidden.toml"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            expand(&dir, "*.toml").unwrap(),
+            [dir.join("a.toml"), dir.join("b.toml")]
+        );
+        assert_eq!(
+            expand(&dir, "?.*").unwrap(),
+            [dir.join("a.toml"), dir.join("b.toml"), dir.join("b.yaml")]
+        );
+        assert_eq!(expand(&dir, ".*.toml").unwrap(), [dir.join(".hidden.toml")]);
+        assert_eq!(
+            expand(&dir, "conf.d/*.toml").unwrap(),
+            Vec::<PathBuf>::new()
+        );
+        assert_eq!(
+            expand(&dir, "*/base.toml").unwrap_err(),
+            "include */base.toml: wildcards are only allowed in file names"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn matches_patterns() {
+        let matches = |pattern: &str, name: &str| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let name: Vec<char> = name.chars().collect();
+            matches_pattern(&pattern, &name)
+        };
+        assert!(matches("*", ""));
+        assert!(matches("*.toml", "base.toml"));
+        assert!(matches("a*b*c", "abbbc"));
+        assert!(matches("?.toml", "a.toml"));
+        assert!(!matches("?.toml", ".toml"));
+        assert!(!matches("*.toml", "base.yaml"));
+        assert!(!matches("base", "base.toml"));
+    }
+}
@@ -0,0 +1,104 @@
+// Expands `${VAR}` and `${VAR:-default}`, where the default is used if VAR is
+// unset or empty. `$${` stands for a literal `${`, and any other `$` is left
+// alone for the shell to deal with.
+pub fn expand(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("unterminated `${{` in `{}`", text))?;
+            let reference = &after[..end];
+            let (name, default) = match reference.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (reference, None),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!(
+                    "invalid variable reference `${{{}}}`, use `$${{` for a literal `${{`",
+                    reference
+                ));
+            }
+            let value = match (lookup(name), default) {
+                (Some(value), Some(default)) if value.is_empty() => default.to_owned(),
+                (Some(value), _) => value,
+                (None, Some(default)) => default.to_owned(),
+                (None, None) => return Err(format!("variable {} is not set", name)),
+            };
+            expanded.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_with(text: &str) -> Result<String, String> {
+        expand(text, |name| match name {
+            "HOST" => Some("example.com".to_owned()),
+            "PORT" => Some("8080".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            expand_with("http://${HOST}:${PORT}/"),
+            Ok("http://example.com:8080/".to_owned())
+        );
+        assert_eq!(expand_with("${PORT}${PORT}"), Ok("80808080".to_owned()));
+        assert_eq!(expand_with("${EMPTY}"), Ok(String::new()));
+        assert_eq!(expand_with("no references"), Ok("no references".to_owned()));
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(expand_with("${UNSET:-80}"), Ok("80".to_owned()));
+        assert_eq!(expand_with("${EMPTY:-80}"), Ok("80".to_owned()));
+        assert_eq!(expand_with("${PORT:-80}"), Ok("8080".to_owned()));
+        assert_eq!(expand_with("${UNSET:-}"), Ok(String::new()));
+        assert_eq!(expand_with("${UNSET:-a:-b}"), Ok("a:-b".to_owned()));
+    }
+
+    #[test]
+    fn other_dollars_are_left_alone() {
+        assert_eq!(expand_with("$HOME $1 $ $"), Ok("$HOME $1 $ $".to_owned()));
+        assert_eq!(expand_with("$${HOST}"), Ok("${HOST}".to_owned()));
+        assert_eq!(expand_with("$$${HOST}"), Ok("$${HOST}".to_owned()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            expand_with("${UNSET}"),
+            Err("variable UNSET is not set".to_owned())
+        );
+        assert_eq!(
+            expand_with("a ${HOST"),
+            Err("unterminated `${` in `a ${HOST`".to_owned())
+        );
+        assert_eq!(
+            expand_with("${}"),
+            Err("invalid variable reference `${}`, use `$${` for a literal `${`".to_owned())
+        );
+        assert_eq!(
+            expand_with("${A-B}"),
+            Err("invalid variable reference `${A-B}`, use `$${` for a literal `${`".to_owned())
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use crate::config::{Overlap, ProcessSpec};
+use crate::error::Error;
+use crate::event::Event;
+use crate::logfile::LogFile;
+use crate::process::{self, Exit};
+use crate::schedule::Schedule;
+use crate::signal::{self, Signal};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use libc::SIGKILL;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug)]
+struct State {
+    // One pid per run still going, oldest first.
+    pids: Vec<u32>,
+    is_started: bool,
+    is_stopping: bool,
+    is_done: bool,
+    // Removed by a reload, so its end does not count as an exit.
+    is_retired: bool,
+    log_file: Option<Arc<Mutex<LogFile>>>,
+}
+
+// A process that is run on a schedule instead of being kept running. As
+// long as it is scheduled a job counts as one process, so its runs exiting
+// never shut the container down. Once it has been stopped and its last run
+// is over it sends its one message on exit_tx.
+#[derive(Debug)]
+pub struct Job {
+    name: String,
+    spec: ProcessSpec,
+    state: Mutex<State>,
+    state_changed: Condvar,
+    stop_tx: Sender<()>,
+    stop_rx: Receiver<()>,
+    exit_tx: Sender<Exit>,
+}
+
+impl Job {
+    pub fn new(name: String, spec: ProcessSpec, exit_tx: Sender<Exit>) -> Self {
+        let (stop_tx, stop_rx) = bounded::<()>(1);
+
+        Self {
+            name,
+            spec,
+            state: Mutex::new(State {
+                pids: Vec::new(),
+                is_started: false,
+                is_stopping: false,
+                is_done: false,
+                is_retired: false,
+                log_file: None,
+            }),
+            state_changed: Condvar::new(),
+            stop_tx,
+            stop_rx,
+            exit_tx,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn spec(&self) -> &ProcessSpec {
+        &self.spec
+    }
+
+    pub fn start(self: &Arc<Self>) {
+        {
+            let mut state = self.lock_state();
+            if state.is_started || state.is_stopping {
+                return;
+            }
+            state.is_started = true;
+        }
+        let job = self.clone();
+        thread::spawn(move || job.run_on_schedule());
+    }
+
+    // Cancels any future runs and sends the signal to the ones still going.
+    // A job that was never started reports its exit right away.
+    pub fn stop(&self, signal: Signal) {
+        let owes_exit = {
+            let mut state = self.lock_state();
+            if state.is_stopping {
+                return;
+            }
+            state.is_stopping = true;
+            self.signal_runs(&state.pids, self.spec.stop_signal.unwrap_or(signal));
+            if !state.is_started {
+                state.is_done = true;
+            }
+            !state.is_started && !state.is_retired
+        };
+        let _ = self.stop_tx.try_send(());
+        self.state_changed.notify_all();
+        if owes_exit {
+            self.send_exit();
+        }
+    }
+
+    // Sends the signal to every run still going. Returns false if there are
+    // none.
+    pub fn send_signal(&self, signal: Signal) -> bool {
+        let state = self.lock_state();
+        self.signal_runs(&state.pids, signal);
+        !state.pids.is_empty()
+    }
+
+    // Forwards a non-terminating signal, translated through signal_map.
+    pub fn forward(&self, signal: Signal) {
+        if let Some(signal) = process::map_signal(&self.spec, signal) {
+            self.send_signal(signal);
+        }
+    }
+
+    // Stops the job for good without it counting as exited, so that a
+    // reload can remove or replace it. Returns false if it is already being
+    // stopped.
+    pub fn retire(&self) -> bool {
+        {
+            let mut state = self.lock_state();
+            if state.is_stopping {
+                return false;
+            }
+            state.is_retired = true;
+        }
+        self.stop(libc::SIGTERM);
+        let mut state = self.lock_state();
+        while !state.is_done {
+            state = self
+                .state_changed
+                .wait(state)
+                .expect("failed to wait on job state");
+        }
+        true
+    }
+
+    // What the control socket reports, along with the pid of the latest run.
+    pub fn status(&self) -> (&'static str, Option<u32>) {
+        let state = self.lock_state();
+        let status = if state.is_done {
+            "exited"
+        } else if state.is_stopping {
+            "stopping"
+        } else if !state.pids.is_empty() {
+            "running"
+        } else {
+            "scheduled"
+        };
+        (status, state.pids.last().copied())
+    }
+
+    fn run_on_schedule(self: &Arc<Self>) {
+        let schedule = self
+            .spec
+            .schedule
+            .as_ref()
+            .expect("jobs are only created for scheduled processes");
+        while let Some(delay) = next_delay(schedule) {
+            match self.stop_rx.recv_timeout(delay) {
+                Err(RecvTimeoutError::Timeout) => self.run(),
+                _ => break,
+            }
+        }
+        if !self.lock_state().is_stopping {
+            Event::new("unscheduled")
+                .process(&self.name)
+                .log(format_args!("{} has no upcoming runs", self.name));
+            let _ = self.stop_rx.recv();
+        }
+        self.finish_runs();
+
+        let is_retired = {
+            let mut state = self.lock_state();
+            state.is_done = true;
+            state.is_retired
+        };
+        self.state_changed.notify_all();
+        if !is_retired {
+            self.send_exit();
+        }
+    }
+
+    fn run(self: &Arc<Self>) {
+        let mut state = self.lock_state();
+        if state.is_stopping {
+            return;
+        }
+        if self.spec.overlap == Overlap::Skip && !state.pids.is_empty() {
+            Event::new("skipped").process(&self.name).log(format_args!(
+                "{} is still running, skipping this run",
+                self.name
+            ));
+            return;
+        }
+        if state.log_file.is_none() {
+            match process::open_log_file(&self.spec) {
+                Ok(log_file) => state.log_file = log_file,
+                Err(source) => return self.spawn_failed(source),
+            }
+        }
+        let (pid, exit_rx) =
+            match process::spawn_child(&self.name, &self.spec, &state.log_file, "spawned") {
+                Ok(child) => child,
+                Err(source) => return self.spawn_failed(source),
+            };
+        state.pids.push(pid);
+
+        let job = self.clone();
+        thread::spawn(move || {
+            let exit_status = exit_rx
+                .recv()
+                .unwrap_or_else(|_| panic!("failed to wait on {}", job.name));
+            Event::new("exited")
+                .process(&job.name)
+                .pid(pid)
+                .exit_status(exit_status)
+                .log(format_args!("{} exited with: {}", job.name, exit_status));
+            job.lock_state().pids.retain(|running| *running != pid);
+            job.state_changed.notify_all();
+        });
+    }
+
+    // A run that fails to start is logged and otherwise ignored, like one
+    // that fails once it is running.
+    fn spawn_failed(&self, source: io::Error) {
+        let err = Error::Spawn {
+            name: self.name.clone(),
+            command: self.spec.command.clone(),
+            source,
+        };
+        Event::new("spawn_failed")
+            .process(&self.name)
+            .log(format_args!("{}", err));
+    }
+
+    // Waits for the runs still going after stop, sending SIGKILL to whatever
+    // is left once stop_timeout has passed.
+    fn finish_runs(&self) {
+        let stop_timeout = Duration::from_secs_f64(
+            self.spec
+                .stop_timeout
+                .expect("stop_timeout is set when reading the config"),
+        );
+        let (state, _) = self
+            .state_changed
+            .wait_timeout_while(self.lock_state(), stop_timeout, |state| {
+                !state.pids.is_empty()
+            })
+            .expect("failed to wait on job state");
+        for pid in &state.pids {
+            Event::new("signaled")
+                .process(&self.name)
+                .pid(*pid)
+                .signal(SIGKILL)
+                .log(format_args!(
+                    "{} did not exit within {:.1}s, sending SIGKILL",
+                    self.name,
+                    stop_timeout.as_secs_f64()
+                ));
+            process::kill_process(*pid, SIGKILL, self.spec.kill_mode);
+        }
+        drop(
+            self.state_changed
+                .wait_while(state, |state| !state.pids.is_empty())
+                .expect("failed to wait on job state"),
+        );
+    }
+
+    fn signal_runs(&self, pids: &[u32], signal: Signal) {
+        for pid in pids {
+            Event::new("signaled")
+                .process(&self.name)
+                .pid(*pid)
+                .signal(signal)
+                .log(format_args!(
+                    "sending {} to {}",
+                    signal::name(signal),
+                    self.name
+                ));
+            process::kill_process(*pid, signal, self.spec.kill_mode);
+        }
+    }
+
+    fn send_exit(&self) {
+        self.exit_tx
+            .send(Exit {
+                name: self.name.clone(),
+                code: None,
+                is_failure: false,
+                is_completed: false,
+            })
+            .expect("failed to send exit message in Job");
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("failed to lock job state")
+    }
+}
+
+// The wall clock is read again before every run, so a clock that jumps only
+// delays the next run rather than skipping all future ones.
+fn next_delay(schedule: &Schedule) -> Option<Duration> {
+    let now = SystemTime::now();
+    let next = schedule.next_after(now)?;
+    Some(next.duration_since(now).unwrap_or_default())
+}
@@ -0,0 +1,410 @@
+use crate::position::{Position, Positions};
+use std::fmt;
+use toml::value::{Table, Value};
+
+// Parses a JSON document into the same value tree a TOML file produces, so
+// both go through the same deserialization. TOML has no null, so null
+// members are left out of objects and rejected anywhere else. Along with
+// the value comes where each key was written.
+pub fn parse(contents: &str) -> Result<(Value, Positions), String> {
+    let mut parser = Parser {
+        chars: contents.chars().collect(),
+        pos: 0,
+        path: Vec::new(),
+        positions: Positions::new(),
+    };
+    parser.skip_whitespace();
+    let value = match parser.peek() {
+        Some('{') => parser.object()?,
+        _ => return Err(parser.error("expected an object")),
+    };
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok((value, parser.positions))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    // The keys leading to the value being parsed.
+    path: Vec<String>,
+    positions: Positions,
+}
+
+impl Parser {
+    fn error(&self, message: impl fmt::Display) -> String {
+        format!("{} at {}", message, self.position())
+    }
+
+    fn position(&self) -> Position {
+        let consumed = &self.chars[..self.pos.min(self.chars.len())];
+        Position {
+            line: consumed.iter().filter(|&&c| c == '\n').count() + 1,
+            column: Some(consumed.iter().rev().take_while(|&&c| c != '\n').count() + 1),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected `{}`, found `{}`", expected, c))),
+            None => Err(self.error(format!("expected `{}`, found end of input", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Option<Value>, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object().map(Some),
+            Some('[') => self.array().map(Some),
+            Some('"') => self.string().map(|string| Some(Value::String(string))),
+            Some('-' | '0'..='9') => self.number().map(Some),
+            Some(c) if c.is_ascii_alphabetic() => self.literal(),
+            Some(c) => Err(self.error(format!("unexpected character `{}`", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut table = Table::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key_pos = self.pos;
+            let key_position = self.position();
+            let key = self.string()?;
+            self.expect(':')?;
+            self.path.push(key.clone());
+            self.positions.insert(&self.path, key_position);
+            let value = self.value()?;
+            self.path.pop();
+            if table.contains_key(&key) {
+                self.pos = key_pos;
+                return Err(self.error(format!("duplicate key `{}`", key)));
+            }
+            if let Some(value) = value {
+                table.insert(key, value);
+            }
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Table(table)),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected `,` or `}`"));
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut array = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(array));
+        }
+        loop {
+            self.skip_whitespace();
+            let item_pos = self.pos;
+            match self.value()? {
+                Some(value) => array.push(value),
+                None => {
+                    self.pos = item_pos;
+                    return Err(self.error("null is not supported in arrays"));
+                }
+            }
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(array)),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected `,` or `]`"));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => string.push(self.unicode_escape()?),
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error("invalid escape"));
+                    }
+                },
+                Some(c) if c < ' ' => {
+                    self.pos -= 1;
+                    return Err(self.error("control character in string"));
+                }
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // A \u escape, which may be the first half of a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+        if self.next() != Some('\\') || self.next() != Some('u') {
+            return Err(self.error("unpaired surrogate in unicode escape"));
+        }
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error("unpaired surrogate in unicode escape"));
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+            .ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.iter().skip(self.pos).take(4).collect();
+        let code = u32::from_str_radix(&digits, 16)
+            .ok()
+            .filter(|_| digits.len() == 4)
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let mut is_float = false;
+        while let Some(c) = self.peek() {
+            match c {
+                '0'..='9' | '-' | '+' => {}
+                '.' | 'e' | 'E' => is_float = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let number: String = self.chars[start..self.pos].iter().collect();
+        let value = if is_float {
+            number.parse().map(Value::Float).ok()
+        } else {
+            number.parse().map(Value::Integer).ok()
+        };
+        value.ok_or_else(|| {
+            self.pos = start;
+            self.error(format!("invalid number `{}`", number))
+        })
+    }
+
+    fn literal(&mut self) -> Result<Option<Value>, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        let literal: String = self.chars[start..self.pos].iter().collect();
+        match literal.as_str() {
+            "true" => Ok(Some(Value::Boolean(true))),
+            "false" => Ok(Some(Value::Boolean(false))),
+            "null" => Ok(None),
+            _ => {
+                self.pos = start;
+                Err(self.error(format!("unexpected `{}`", literal)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(json: &str) -> Value {
+        parse(json).unwrap().0
+    }
+
+    fn toml(toml: &str) -> Value {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn error(json: &str) -> String {
+        parse(json).unwrap_err()
+    }
+
+    #[test]
+    fn objects_and_arrays() {
+        let json = r#"{
+            "processes": {
+                "web": {
+                    "command": ["python3", "-m", "http.server"],
+                    "depends_on": [],
+                    "ready": { "tcp": 8000 }
+                }
+            }
+        }"#;
+        let expected = r#"
+[processes.web]
+command = ["python3", "-m", "http.server"]
+depends_on = []
+ready = { tcp = 8000 }
+"#;
+        assert_eq!(value(json), toml(expected));
+    }
+
+    #[test]
+    fn scalars() {
+        let json = r#"{"int": -42, "float": 2.5, "exponent": 1e3, "yes": true, "no": false}"#;
+        let expected = r#"
+int = -42
+float = 2.5
+exponent = 1000.0
+yes = true
+no = false
+"#;
+        assert_eq!(value(json), toml(expected));
+    }
+
+    #[test]
+    fn escapes() {
+        let json = r#"{"a": "\"q\" \\ \/ \b\f\n\r\t é 😀"}"#;
+        assert_eq!(
+            value(json)["a"].as_str(),
+            Some("\"q\" \\ / \u{8}\u{c}\n\r\t é 😀")
+        );
+    }
+
+    #[test]
+    fn bad_escapes() {
+        assert_eq!(error(r#"{"a": "\q"}"#), "invalid escape at line 1 column 9");
+        assert_eq!(
+            error(r#"{"a": "\u12"}"#),
+            "invalid unicode escape at line 1 column 10"
+        );
+        assert_eq!(
+            error(r#"{"a": "\ud83d"}"#),
+            "unpaired surrogate in unicode escape at line 1 column 15"
+        );
+        assert_eq!(
+            error("{\"a\": \"line\nbreak\"}"),
+            "control character in string at line 1 column 12"
+        );
+    }
+
+    #[test]
+    fn nulls_are_left_out_of_objects() {
+        assert_eq!(value(r#"{"a": null, "b": 1}"#), toml("b = 1"));
+        assert_eq!(
+            error(r#"{"a": [1, null]}"#),
+            "null is not supported in arrays at line 1 column 11"
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert_eq!(
+            error("{\n  \"a\": 1,\n  \"a\": 2\n}"),
+            "duplicate key `a` at line 3 column 3"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("[1]"), "expected an object at line 1 column 1");
+        assert_eq!(error("{} {}"), "trailing characters at line 1 column 4");
+        assert_eq!(
+            error(r#"{"a": 1,}"#),
+            "expected a string key at line 1 column 9"
+        );
+        assert_eq!(
+            error(r#"{"a" 1}"#),
+            "expected `:`, found `1` at line 1 column 6"
+        );
+        assert_eq!(
+            error(r#"{"a": 1 "b": 2}"#),
+            "expected `,` or `}` at line 1 column 9"
+        );
+        assert_eq!(
+            error(r#"{"a": [1 2]}"#),
+            "expected `,` or `]` at line 1 column 10"
+        );
+        assert_eq!(
+            error(r#"{"a": tru}"#),
+            "unexpected `tru` at line 1 column 7"
+        );
+        assert_eq!(
+            error(r#"{"a": 1.2.3}"#),
+            "invalid number `1.2.3` at line 1 column 7"
+        );
+        assert_eq!(
+            error(r#"{"a": "open"#),
+            "unterminated string at line 1 column 12"
+        );
+        assert_eq!(
+            error(r#"{"a": "#),
+            "unexpected end of input at line 1 column 7"
+        );
+    }
+
+    #[test]
+    fn comments_are_rejected() {
+        assert_eq!(
+            error("{\n  // no\n}"),
+            "expected a string key at line 2 column 3"
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let json = "{\n  \"processes\": {\n    \"web\": { \"env\": { \"B\": 2 } }\n  }\n}";
+        let (_, positions) = parse(json).unwrap();
+        let position = |path: &str| {
+            positions
+                .get(path)
+                .map(|position| (position.line, position.column))
+        };
+        assert_eq!(position("processes"), Some((2, Some(3))));
+        assert_eq!(position("processes.web"), Some((3, Some(5))));
+        assert_eq!(position("processes.web.env.B"), Some((3, Some(23))));
+    }
+}
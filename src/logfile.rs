@@ -0,0 +1,224 @@
+use crate::event::Event;
+use crate::reaper;
+use crossbeam_channel::{unbounded, Sender};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+const COMPRESS_TIMEOUT: Duration = Duration::from_secs(600);
+
+// A process's log file. Once it grows past max_size it is renamed to
+// `<path>.1`, older files move up by one and anything past max_files is
+// removed. Rotated files are optionally gzipped.
+//
+// Writes happen with the log file locked, and a gzip can take minutes, so
+// only the rename of the live file happens in write_line. It is renamed to a
+// numbered staging file, and a background thread moves the older files up,
+// gives the staging file its place as `<path>.1` and compresses it, one
+// rotation at a time.
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_files: usize,
+    compress: bool,
+    file: File,
+    size: u64,
+    rotations: Option<Sender<PathBuf>>,
+    staged: u64,
+}
+
+impl LogFile {
+    pub fn open(
+        path: &Path,
+        max_size: Option<u64>,
+        max_files: usize,
+        compress: bool,
+    ) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_owned(),
+            max_size,
+            max_files,
+            compress,
+            file,
+            size,
+            rotations: None,
+            staged: 0,
+        })
+    }
+
+    pub fn write_line(&mut self, line: &[u8]) {
+        let result = self
+            .file
+            .write_all(line)
+            .and_then(|()| self.file.write_all(b"\n"));
+        if let Err(err) = result {
+            Event::new("log_error")
+                .string("path", &self.path.to_string_lossy())
+                .log(format_args!(
+                    "failed to write to {}: {}",
+                    self.path.display(),
+                    err
+                ));
+            return;
+        }
+        self.size += line.len() as u64 + 1;
+
+        if self.max_size.is_some_and(|max_size| self.size >= max_size) {
+            if let Err(err) = self.rotate() {
+                log_rotate_error(&self.path, err);
+            }
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            self.staged += 1;
+            let staged = suffixed(&self.path, &format!(".rotating{}", self.staged));
+            fs::rename(&self.path, &staged)?;
+            let (path, max_files, compress) = (&self.path, self.max_files, self.compress);
+            let rotations = self
+                .rotations
+                .get_or_insert_with(|| rotate_in_background(path.clone(), max_files, compress));
+            // The thread only stops once the sender is dropped, so this
+            // cannot fail.
+            let _ = rotations.send(staged);
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+// Takes staged files in the order they were rotated out and exits once the
+// log file is dropped.
+fn rotate_in_background(path: PathBuf, max_files: usize, compress: bool) -> Sender<PathBuf> {
+    let (staged_tx, staged_rx) = unbounded::<PathBuf>();
+    thread::spawn(move || {
+        for staged in staged_rx {
+            match shift(&path, max_files, &staged) {
+                Ok(rotated) if compress => gzip(&rotated),
+                Ok(_) => {}
+                Err(err) => log_rotate_error(&path, err),
+            }
+        }
+    });
+    staged_tx
+}
+
+// Moves `<path>.N` and `<path>.N.gz` up by one, dropping those past
+// max_files, and renames the staged file to `<path>.1`.
+fn shift(path: &Path, max_files: usize, staged: &Path) -> io::Result<PathBuf> {
+    for index in (1..=max_files).rev() {
+        for extension in &["", ".gz"] {
+            let from = suffixed(path, &format!(".{}{}", index, extension));
+            if !from.exists() {
+                continue;
+            }
+            if index == max_files {
+                fs::remove_file(&from)?;
+            } else {
+                fs::rename(
+                    &from,
+                    suffixed(path, &format!(".{}{}", index + 1, extension)),
+                )?;
+            }
+        }
+    }
+    let rotated = suffixed(path, ".1");
+    fs::rename(staged, &rotated)?;
+    Ok(rotated)
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_owned().into_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn log_rotate_error(path: &Path, err: io::Error) {
+    Event::new("log_error")
+        .string("path", &path.to_string_lossy())
+        .log(format_args!("failed to rotate {}: {}", path.display(), err));
+}
+
+fn gzip(path: &Path) {
+    let mut command = Command::new("gzip");
+    command
+        .arg("-f")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null());
+    let event = Event::new("log_error").string("path", &path.to_string_lossy());
+    match reaper::run(&mut command, COMPRESS_TIMEOUT) {
+        Ok(Some(exit_status)) if exit_status.success() => {}
+        Ok(Some(exit_status)) => event.exit_status(exit_status).log(format_args!(
+            "gzip {} exited with: {}",
+            path.display(),
+            exit_status
+        )),
+        Ok(None) => event.log(format_args!("gzip {} timed out", path.display())),
+        Err(err) => event.log(format_args!(
+            "failed to run gzip on {}: {}",
+            path.display(),
+            err
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::time::Instant;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("spot-init-logfile-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Rotation finishes in the background.
+    fn wait_until(done: impl Fn() -> bool) {
+        let started = Instant::now();
+        while !done() {
+            assert!(started.elapsed() < Duration::from_secs(10), "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn rotates_and_drops_the_oldest_file() {
+        let dir = scratch_dir("rotate");
+        let path = dir.join("app.log");
+        let mut log_file = LogFile::open(&path, Some(4), 2, false).unwrap();
+        for line in ["one", "two", "three", "four", "5"] {
+            log_file.write_line(line.as_bytes());
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap_or_default();
+        wait_until(|| read("app.log.1") == "four\n");
+        assert_eq!(read("app.log.2"), "three\n");
+        assert_eq!(read("app.log"), "5\n");
+        assert!(!dir.join("app.log.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,19 +1,49 @@
+mod check;
+mod command;
+mod config;
+mod control;
+mod envfile;
+mod error;
+mod event;
+mod include;
+mod interpolate;
+mod job;
+mod json;
+mod logfile;
+mod output;
+mod position;
+mod probe;
 mod process;
+mod procfile;
+mod reaper;
+mod restart;
+mod rlimit;
+mod schedule;
+mod signal;
+mod supervisor;
+mod user;
+mod yaml;
 
-use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
+use clap::{
+    app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg, ArgMatches,
+    SubCommand,
+};
+use config::{load_config, ColorMode, ExitCodePolicy, Format};
 use crossbeam_channel::bounded;
 use crossbeam_channel::Sender;
-use serde_derive::Deserialize;
+use error::Error;
+use event::Event;
+use process::{Exit, Process};
+use signal::Signal;
 use signal_hook::iterator::Signals;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::prelude::*;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
-use process::Process;
-
-type Signal = i32;
+use std::time::Duration;
+use supervisor::Supervisor;
 
 static CHILD_PROCESS_COUNT: AtomicUsize = AtomicUsize::new(0);
 static IS_SIGNALED: AtomicBool = AtomicBool::new(false);
@@ -23,100 +53,318 @@ fn main() {
         .arg(
             Arg::with_name("config")
                 .default_value("init.toml")
-                .help("Path to config file.")
+                .help("Path to config file, or a directory of them.")
                 .required(true),
         )
+        .arg(format_arg())
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of spot-init's own log lines."),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Checks a config file without starting anything.")
+                .arg(
+                    Arg::with_name("config")
+                        .default_value("init.toml")
+                        .help("Path to config file, or a directory of them."),
+                )
+                .arg(format_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("ctl")
+                .about("Controls a running spot-init through its control socket.")
+                .arg(
+                    Arg::with_name("socket")
+                        .long("socket")
+                        .takes_value(true)
+                        .default_value(control::DEFAULT_SOCKET)
+                        .help("Path to the control socket."),
+                )
+                .arg(
+                    Arg::with_name("request")
+                        .required(true)
+                        .multiple(true)
+                        .help("status, reload, start <name>, stop <name>, restart <name> or signal <name> <signal>"),
+                ),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let config_path = matches
+            .value_of("config")
+            .expect("failed to read config arg");
+        std::process::exit(check::run(config_path, format(matches)));
+    }
+    if let Some(matches) = matches.subcommand_matches("ctl") {
+        std::process::exit(ctl(matches));
+    }
+
+    event::use_json(matches.value_of("log-format") == Some("json"));
+
     let config_path = matches
         .value_of("config")
         .expect("failed to read config arg");
 
-    let config = read_config(config_path);
+    let exit_code = run(config_path, format(&matches)).unwrap_or_else(|err| {
+        Event::new("error")
+            .number("exit_code", i64::from(err.exit_code()))
+            .log(format_args!("{}", err));
+        err.exit_code()
+    });
+    Event::new("exiting")
+        .number("exit_code", i64::from(exit_code))
+        .log(format_args!("exiting with {}", exit_code));
+    std::process::exit(exit_code);
+}
+
+// Supervises the processes until they have all exited, returning the exit
+// code for spot-init. If a process fails to start, the ones already started
+// are stopped again before its error is returned.
+fn run(config_path: &str, format: Option<Format>) -> Result<i32, Error> {
+    let config = load_config(config_path, format).map_err(Error::Config)?;
 
-    println!("{:?}", config);
+    Event::new("config_loaded")
+        .string("path", config_path)
+        .number("processes", config.processes.len() as i64)
+        .log(format_args!(
+            "loaded {} with {} processes",
+            config_path,
+            config.processes.len()
+        ));
 
-    let (exit_tx, exit_rx) = bounded::<()>(0);
+    let use_color = match config.log_color {
+        ColorMode::Auto => output::stdout_is_terminal(),
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+    };
+    let name_width = config.processes.keys().map(String::len).max().unwrap_or(0);
+    output::configure(use_color, config.log_timestamps, name_width);
 
-    let processes: Vec<Process> = config
-        .processes
-        .iter()
-        .map(|proc_def| {
-            CHILD_PROCESS_COUNT.fetch_add(1, Ordering::Relaxed);
-            let (name, command) = proc_def;
-            Process::new(name.clone(), command.clone(), exit_tx.clone())
-        })
-        .collect();
+    let start_order = config
+        .validate()
+        .and_then(|()| config.start_order())
+        .map_err(Error::Config)?;
 
-    let processes_thread_safe = Arc::new(processes);
+    reaper::start()
+        .map_err(|err| Error::Runtime(format!("failed to register SIGCHLD handler: {}", err)))?;
 
-    let is_pid1 = std::process::id() == 1;
-    if is_pid1 {
-        println!("running as pid1");
+    let (exit_tx, exit_rx) = bounded::<Exit>(0);
+
+    let supervisor = Arc::new(Supervisor::new(
+        config_path,
+        format,
+        &config,
+        &start_order,
+        exit_tx,
+    ));
+
+    if std::process::id() == 1 {
+        Event::new("pid1").log(format_args!("running as pid1"));
+    }
+
+    let mut control_socket = None;
+    if !config.control_socket.as_os_str().is_empty() {
+        match control::listen(&config.control_socket, supervisor.clone()) {
+            Ok(()) => control_socket = Some(config.control_socket),
+            Err(err) => Event::new("control_error")
+                .string("path", &config.control_socket.to_string_lossy())
+                .log(format_args!(
+                    "failed to listen on {}: {}",
+                    config.control_socket.display(),
+                    err
+                )),
+        }
     }
 
+    let exit_code_policy = config.exit_code_policy;
+    let main_process = config.main;
     let (signal_tx, signal_rx) = bounded::<Signal>(0);
     let signal_tx_clone = signal_tx.clone();
-    register_sig_handler(signal_tx_clone, is_pid1);
+    register_sig_handler(signal_tx_clone)
+        .map_err(|err| Error::Runtime(format!("failed to register signal handler: {}", err)))?;
 
+    let supervisor_clone = supervisor.clone();
     thread::spawn(move || {
-        let signal = signal_rx
-            .recv()
-            .expect("failed to receive signal message in main");
-        processes_thread_safe.iter().for_each(|process| {
-            process.send_signal(signal);
+        signal_rx.iter().for_each(|signal| {
+            if signal::is_terminating(signal) {
+                for job in supervisor_clone.jobs() {
+                    job.stop(signal);
+                }
+                stop_in_reverse_order(&supervisor_clone.processes(), signal);
+            } else if supervisor_clone.reload_signal() == Some(signal) {
+                // A reload can take a while, and must not hold up a shutdown.
+                let supervisor = supervisor_clone.clone();
+                thread::spawn(move || supervisor.reload());
+            } else {
+                forward(&supervisor_clone, signal);
+            }
         })
     });
     let signal_tx_clone = signal_tx.clone();
-    thread::spawn(move || {
+    let exit_loop = thread::spawn(move || {
+        let mut first_failure = None;
+        let mut main_exit_code = None;
         loop {
-            exit_rx.recv().expect("failed to receive exit message");
+            let exit = exit_rx.recv().expect("failed to receive exit message");
+            if exit.is_failure && first_failure.is_none() {
+                first_failure = exit.code;
+            }
+            if main_process.as_ref() == Some(&exit.name) {
+                main_exit_code = exit.code;
+            }
             let remaining_processes = CHILD_PROCESS_COUNT.fetch_sub(1, Ordering::Relaxed) - 1;
             if remaining_processes == 0 {
                 break;
             }
-            if !IS_SIGNALED.load(Ordering::Relaxed) {
+            if !exit.is_completed && !IS_SIGNALED.swap(true, Ordering::Relaxed) {
                 signal_tx_clone
                     .send(signal_hook::SIGTERM)
                     .expect("failed to send signal message based on exit message");
             }
         }
-        println!("all subprocesses have exited");
-    })
-    .join()
-    .expect("failed to join exit loop thread");
+        Event::new("all_exited").log(format_args!("all subprocesses have exited"));
+        match exit_code_policy {
+            ExitCodePolicy::FirstFailure => first_failure.unwrap_or(0),
+            ExitCodePolicy::Main => main_exit_code.or(first_failure).unwrap_or(0),
+        }
+    });
+
+    let started = start_in_order(&supervisor.processes());
+    supervisor.finish_starting();
+    if started.is_err() && !IS_SIGNALED.swap(true, Ordering::Relaxed) {
+        signal_tx
+            .send(signal_hook::SIGTERM)
+            .expect("failed to send signal message after failed start");
+    }
+
+    let exit_code = exit_loop.join().expect("failed to join exit loop thread");
+    output::drain(Duration::from_secs(1));
+    if let Some(control_socket) = control_socket {
+        let _ = fs::remove_file(control_socket);
+    }
+    started.map(|()| exit_code)
+}
+
+fn format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(Format::NAMES)
+        .help("Format of the config file, by default picked by its name.")
+}
+
+fn format(matches: &ArgMatches) -> Option<Format> {
+    matches
+        .value_of("format")
+        .map(|name| Format::from_name(name).expect("clap only accepts known formats"))
+}
+
+fn ctl(matches: &ArgMatches) -> i32 {
+    let socket = Path::new(
+        matches
+            .value_of("socket")
+            .expect("failed to read socket arg"),
+    );
+    let request = matches
+        .values_of("request")
+        .expect("failed to read request arg")
+        .collect::<Vec<_>>()
+        .join(" ");
+    match control::request(socket, &request) {
+        Ok(reply) => {
+            print!("{}", reply);
+            if reply.starts_with("error: ") {
+                1
+            } else {
+                0
+            }
+        }
+        Err(err) => {
+            eprintln!("control request to {} failed: {}", socket.display(), err);
+            1
+        }
+    }
+}
+
+// A process is only started once everything it depends on is ready. If a
+// dependency never gets there it has already been stopped, which shuts down
+// everything else, so there is nothing left to start. The same goes for
+// anything still waiting to start once a process has exited, such as a
+// failed oneshot.
+fn start_in_order(processes: &[Arc<Process>]) -> Result<(), Error> {
+    for (index, process) in processes.iter().enumerate() {
+        if IS_SIGNALED.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let unready = processes[..index]
+            .iter()
+            .filter(|dependency| process.depends_on(dependency.name()))
+            .find(|dependency| !dependency.wait_ready());
+        if let Some(dependency) = unready {
+            Event::new("not_started")
+                .process(process.name())
+                .string("dependency", dependency.name())
+                .log(format_args!(
+                    "not starting {}: {} did not become ready",
+                    process.name(),
+                    dependency.name()
+                ));
+            return Ok(());
+        }
+        process.start()?;
+    }
+    Ok(())
 }
 
-#[derive(Deserialize, Debug)]
-struct Config {
-    processes: HashMap<String, String>,
+// Processes are stopped in reverse start order, and each one only once
+// everything depending on it has exited.
+fn stop_in_reverse_order(processes: &[Arc<Process>], signal: Signal) {
+    for (index, process) in processes.iter().enumerate().rev() {
+        processes[index + 1..]
+            .iter()
+            .filter(|dependent| dependent.depends_on(process.name()))
+            .for_each(|dependent| dependent.wait());
+        process.stop(signal);
+    }
 }
 
-fn read_config(config_path: &str) -> Config {
-    let mut config_file = File::open(config_path).expect("failed to open config file");
-    let mut config_toml_string = String::new();
-    config_file
-        .read_to_string(&mut config_toml_string)
-        .expect("failed to read from config file");
-    toml::from_str(config_toml_string.as_ref()).expect("failed to parse config file")
+fn forward(supervisor: &Supervisor, signal: Signal) {
+    let routes = supervisor.signal_routes();
+    let is_routed_to = |name: &str| match routes.get(&signal) {
+        Some(names) => names.iter().any(|routed| routed == name),
+        None => true,
+    };
+    for process in supervisor.processes() {
+        if is_routed_to(process.name()) {
+            process.forward(signal);
+        }
+    }
+    for job in supervisor.jobs() {
+        if is_routed_to(job.name()) {
+            job.forward(signal);
+        }
+    }
 }
 
-fn register_sig_handler(signal_tx: Sender<Signal>, is_pid1: bool) {
-    let signals = Signals::new(&[
-        signal_hook::SIGTERM,
-        signal_hook::SIGINT,
-        signal_hook::SIGQUIT,
-    ])
-    .expect("failed to register signal handler");
+// Children run in sessions of their own, so nothing reaches them unless it is
+// forwarded, whether or not spot-init is pid 1.
+fn register_sig_handler(signal_tx: Sender<Signal>) -> io::Result<()> {
+    let signals = Signals::new(signal::FORWARDED)?;
 
     thread::spawn(move || {
         signals.forever().for_each(|signal| {
-            IS_SIGNALED.store(true, Ordering::Relaxed);
-            if is_pid1 {
-                signal_tx
-                    .send(signal)
-                    .expect("failed to send signal from handler");
+            if signal::is_terminating(signal) {
+                IS_SIGNALED.store(true, Ordering::Relaxed);
             }
+            signal_tx
+                .send(signal)
+                .expect("failed to send signal from handler");
         });
     });
-}
\ No newline at end of file
+    Ok(())
+}
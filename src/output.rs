@@ -0,0 +1,157 @@
+use crate::logfile::LogFile;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Longer lines are emitted in pieces rather than buffered without bound.
+const MAX_LINE_LENGTH: usize = 16 * 1024;
+
+const COLORS: &[&str] = &["36", "33", "32", "35", "34", "31", "96", "93", "92", "95"];
+
+static USE_COLOR: AtomicBool = AtomicBool::new(false);
+static USE_TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+static NAME_WIDTH: AtomicUsize = AtomicUsize::new(0);
+static ACTIVE_CAPTURES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub fn configure(color: bool, timestamps: bool, name_width: usize) {
+    USE_COLOR.store(color, Ordering::Relaxed);
+    USE_TIMESTAMPS.store(timestamps, Ordering::Relaxed);
+    NAME_WIDTH.store(name_width, Ordering::Relaxed);
+}
+
+pub fn stdout_is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+// Where a captured stream goes: our own stdout or stderr, a log file or both.
+#[derive(Debug, Clone)]
+pub struct Destination {
+    pub stream: Option<Stream>,
+    pub log_file: Option<Arc<Mutex<LogFile>>>,
+}
+
+// Copies a child's output line by line to its destination. Lines on the
+// console are prefixed with the process name.
+pub fn capture<R: Read + Send + 'static>(name: &str, reader: R, destination: Destination) {
+    let prefix = prefix(name);
+    ACTIVE_CAPTURES.fetch_add(1, Ordering::Relaxed);
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            let (consumed, is_eof) = match reader.fill_buf() {
+                Ok([]) => (0, true),
+                Ok(buf) => {
+                    let room = MAX_LINE_LENGTH - line.len();
+                    match buf.iter().take(room).position(|&byte| byte == b'\n') {
+                        Some(index) => {
+                            line.extend_from_slice(&buf[..index]);
+                            write_line(&prefix, &mem::take(&mut line), &destination);
+                            (index + 1, false)
+                        }
+                        None => {
+                            let consumed = buf.len().min(room);
+                            line.extend_from_slice(&buf[..consumed]);
+                            if line.len() == MAX_LINE_LENGTH {
+                                write_line(&prefix, &mem::take(&mut line), &destination);
+                            }
+                            (consumed, false)
+                        }
+                    }
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (0, false),
+                Err(_) => (0, true),
+            };
+            if is_eof {
+                break;
+            }
+            reader.consume(consumed);
+        }
+        if !line.is_empty() {
+            write_line(&prefix, &line, &destination);
+        }
+        ACTIVE_CAPTURES.fetch_sub(1, Ordering::Relaxed);
+    });
+}
+
+// Gives capture threads a chance to write out what exited processes left in
+// their pipes. Orphans can keep a pipe open indefinitely, hence the timeout.
+pub fn drain(timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    while ACTIVE_CAPTURES.load(Ordering::Relaxed) > 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn prefix(name: &str) -> String {
+    let padding = " ".repeat(
+        NAME_WIDTH
+            .load(Ordering::Relaxed)
+            .saturating_sub(name.len()),
+    );
+    if USE_COLOR.load(Ordering::Relaxed) {
+        let color = COLORS[name.bytes().map(usize::from).sum::<usize>() % COLORS.len()];
+        format!("\x1b[{}m[{}]\x1b[0m{} ", color, name, padding)
+    } else {
+        format!("[{}]{} ", name, padding)
+    }
+}
+
+// Each line goes out in a single write so lines from different processes
+// never interleave.
+fn write_line(prefix: &str, line: &[u8], destination: &Destination) {
+    let timestamp = if USE_TIMESTAMPS.load(Ordering::Relaxed) {
+        format!("{} ", timestamp())
+    } else {
+        String::new()
+    };
+
+    if let Some(stream) = destination.stream {
+        let output = format!("{}{}{}\n", timestamp, prefix, String::from_utf8_lossy(line));
+        let _ = match stream {
+            Stream::Stdout => io::stdout().lock().write_all(output.as_bytes()),
+            Stream::Stderr => io::stderr().lock().write_all(output.as_bytes()),
+        };
+    }
+
+    if let Some(log_file) = &destination.log_file {
+        let mut entry = timestamp.into_bytes();
+        entry.extend_from_slice(line);
+        log_file
+            .lock()
+            .expect("failed to lock log file")
+            .write_line(&entry);
+    }
+}
+
+// RFC 3339 in UTC with millisecond precision.
+pub fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe {
+        libc::gmtime_r(&seconds, &mut tm);
+    }
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        now.subsec_millis()
+    )
+}
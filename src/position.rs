@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fmt;
+
+// Where a key was written in a YAML or JSON file. The YAML parser only
+// tracks lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(column) = self.column {
+            write!(f, " column {}", column)?;
+        }
+        Ok(())
+    }
+}
+
+// Positions by dotted key path, the way toml names keys in errors. Array
+// items share their array's path, so the first one wins.
+#[derive(Debug, Default)]
+pub struct Positions(HashMap<String, Position>);
+
+impl Positions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &[String], position: Position) {
+        self.0.entry(path.join(".")).or_insert(position);
+    }
+
+    pub fn extend(&mut self, other: Positions) {
+        for (path, position) in other.0 {
+            self.0.entry(path).or_insert(position);
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<Position> {
+        self.0.get(path).copied()
+    }
+}
+
+// Errors from deserializing a value tree name the key but, unlike those
+// from TOML text, have no line to go with it. This adds the position of the
+// key, or of the closest enclosing one that was written down.
+pub fn locate(err: toml::de::Error, positions: &Positions) -> String {
+    let message = err.to_string();
+    if err.line_col().is_some() {
+        return message;
+    }
+    let key = match message
+        .rsplit_once("for key `")
+        .and_then(|(_, key)| key.strip_suffix('`'))
+    {
+        Some(key) => key,
+        None => return message,
+    };
+    let mut path = key;
+    loop {
+        if let Some(position) = positions.get(path) {
+            return format!("{} at {}", message, position);
+        }
+        match path.rsplit_once('.') {
+            Some((parent, _)) => path = parent,
+            None => return message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml::Value;
+
+    type Tables = HashMap<String, HashMap<String, String>>;
+
+    fn positions() -> Positions {
+        let mut positions = Positions::new();
+        let at = |line| Position { line, column: None };
+        positions.insert(&["a".to_owned()], at(1));
+        positions.insert(&["a".to_owned(), "b".to_owned()], at(2));
+        positions.insert(&["a".to_owned(), "b".to_owned()], at(9));
+        positions
+    }
+
+    fn error(toml: &str) -> toml::de::Error {
+        let value: Value = toml::from_str(toml).unwrap();
+        value.try_into::<Tables>().unwrap_err()
+    }
+
+    #[test]
+    fn adds_the_position_of_the_key() {
+        assert_eq!(
+            locate(error("a = { b = 2 }"), &positions()),
+            "invalid type: integer `2`, expected a string for key `a.b` at line 2"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_an_enclosing_key() {
+        assert_eq!(
+            locate(error("a = { c = 2 }"), &positions()),
+            "invalid type: integer `2`, expected a string for key `a.c` at line 1"
+        );
+    }
+
+    #[test]
+    fn keeps_errors_without_a_known_key() {
+        assert_eq!(
+            locate(error("z = { c = 2 }"), &positions()),
+            "invalid type: integer `2`, expected a string for key `z.c`"
+        );
+    }
+
+    #[test]
+    fn formats_columns() {
+        let position = Position {
+            line: 3,
+            column: Some(7),
+        };
+        assert_eq!(position.to_string(), "line 3 column 7");
+    }
+}
@@ -0,0 +1,76 @@
+use crate::command;
+use crate::config::{Check, CommandLine, Probe, ProcessSpec};
+use crate::reaper;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Stdio;
+use std::time::Duration;
+
+pub fn check(probe: &Probe, spec: &ProcessSpec) -> bool {
+    let timeout = Duration::from_secs_f64(probe.timeout);
+    match &probe.check {
+        Check::Exec(command_line) => exec(command_line, spec, timeout),
+        Check::Tcp(address) => connect(address, timeout).is_some(),
+        Check::Http(url) => http_get(url, timeout),
+        Check::File(path) => path.exists(),
+    }
+}
+
+fn exec(command_line: &CommandLine, spec: &ProcessSpec, timeout: Duration) -> bool {
+    let mut command = match command::build_with(command_line, spec) {
+        Ok(command) => command,
+        Err(_) => return false,
+    };
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    match reaper::run(&mut command, timeout) {
+        Ok(Some(exit_status)) => exit_status.success(),
+        _ => false,
+    }
+}
+
+fn connect(address: &str, timeout: Duration) -> Option<TcpStream> {
+    address
+        .to_socket_addrs()
+        .ok()?
+        .find_map(|address| TcpStream::connect_timeout(&address, timeout).ok())
+}
+
+fn http_get(url: &str, timeout: Duration) -> bool {
+    let url = url.trim_start_matches("http://");
+    let (authority, path) = match url.find('/') {
+        Some(index) => (&url[..index], &url[index..]),
+        None => (url, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_owned()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = match connect(&address, timeout) {
+        Some(stream) => stream,
+        None => return false,
+    };
+    let _ = stream.set_read_timeout(Some(timeout));
+    let _ = stream.set_write_timeout(Some(timeout));
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+
+    let mut status_line = String::new();
+    if BufReader::new(stream).read_line(&mut status_line).is_err() {
+        return false;
+    }
+    match status_line.split_whitespace().nth(1) {
+        Some(code) => code.len() == 3 && code.starts_with('2'),
+        None => false,
+    }
+}
@@ -1,86 +1,760 @@
-use crossbeam_channel::bounded;
-use crossbeam_channel::{Select, Sender};
-use libc::kill;
-use std::process::Command;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use crate::command;
+use crate::config::{CommandLine, KillMode, Probe, ProcessKind, ProcessSpec};
+use crate::error::Error;
+use crate::event::Event;
+use crate::logfile::LogFile;
+use crate::output::{self, Destination, Stream};
+use crate::probe;
+use crate::reaper;
+use crate::restart::Backoff;
+use crate::signal::{self, Signal};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use libc::{kill, SIGKILL};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant};
 
-type Signal = i32;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Readiness {
+    Pending,
+    Ready,
+    Failed,
+}
+
+#[derive(Debug)]
+struct State {
+    pid: Option<u32>,
+    is_started: bool,
+    is_stopping: bool,
+    is_done: bool,
+    // Stopped through the control socket. A held process does not count as
+    // exited and can be resumed.
+    is_held: bool,
+    readiness: Readiness,
+    log_file: Option<Arc<Mutex<LogFile>>>,
+}
 
 #[derive(Debug)]
 pub struct Process {
     name: String,
-    pid: u32,
-    signal_tx: Sender<Signal>,
-    is_alive: Arc<AtomicBool>,
+    spec: ProcessSpec,
+    state: Mutex<State>,
+    state_changed: Condvar,
+    stop_tx: Sender<()>,
+    stop_rx: Receiver<()>,
+    exit_tx: Sender<Exit>,
+}
+
+// Sent on exit_tx once a process is done for good. A process that was never
+// started has no exit code, and exits caused by stopping it are not failures.
+// A oneshot that ran to completion is not a reason to shut down.
+#[derive(Debug)]
+pub struct Exit {
+    pub name: String,
+    pub code: Option<i32>,
+    pub is_failure: bool,
+    pub is_completed: bool,
 }
 
 impl Process {
-    pub fn new(name: String, command: String, exit_tx: Sender<()>) -> Self {
-        let mut child = Command::new("sh")
-            .arg("-c")
-            .arg(&command)
-            .spawn()
-            .expect(&format!("failed to execute {}: `{}`", name, command));
-
-        let pid = child.id();
-        let is_alive = Arc::new(AtomicBool::new(true));
-        let (signal_tx, signal_rx) = bounded::<Signal>(0);
-        let (local_exit_tx, local_exit_rx) = bounded::<()>(0);
-
-        let name_clone = name.clone();
-        thread::spawn(move || loop {
-            let mut select = Select::new();
-            select.recv(&signal_rx);
-            select.recv(&local_exit_rx);
-            select.ready();
-            if let Ok(signal) = signal_rx.try_recv() {
-                let signal_name = match signal {
-                    signal_hook::SIGTERM => "SIGTERM",
-                    signal_hook::SIGINT => "SIGINT",
-                    signal_hook::SIGQUIT => "SIGQUIT",
-                    _ => "SIGUNKNOWN",
-                };
-                println!("sending {} to {}", signal_name, name_clone);
-                unsafe {
-                    kill(pid as i32, signal);
+    // Every process sends exactly one message on exit_tx: when it has exited
+    // for good, or when it is stopped before it was ever started.
+    pub fn new(name: String, spec: ProcessSpec, exit_tx: Sender<Exit>) -> Self {
+        let (stop_tx, stop_rx) = bounded::<()>(1);
+
+        Self {
+            name,
+            spec,
+            state: Mutex::new(State {
+                pid: None,
+                is_started: false,
+                is_stopping: false,
+                is_done: false,
+                is_held: false,
+                readiness: Readiness::Pending,
+                log_file: None,
+            }),
+            state_changed: Condvar::new(),
+            stop_tx,
+            stop_rx,
+            exit_tx,
+        }
+    }
+
+    // A process added by a reload. It starts out stopped, and resume
+    // launches it.
+    pub fn new_stopped(name: String, spec: ProcessSpec, exit_tx: Sender<Exit>) -> Self {
+        let process = Self::new(name, spec, exit_tx);
+        {
+            let mut state = process.lock_state();
+            state.is_done = true;
+            state.is_held = true;
+            state.readiness = Readiness::Failed;
+        }
+        process
+    }
+
+    pub fn start(self: &Arc<Self>) -> Result<(), Error> {
+        self.launch()
+            .map(|_| ())
+            .map_err(|source| self.spawn_error(source))
+    }
+
+    // Returns false if the process was stopped before it could be spawned.
+    fn launch(self: &Arc<Self>) -> io::Result<bool> {
+        let child_exit_rx = match self.spawn()? {
+            Some(child_exit_rx) => child_exit_rx,
+            None => return Ok(false),
+        };
+
+        match &self.spec.ready {
+            Some(probe) => {
+                let process = self.clone();
+                let probe = probe.clone();
+                thread::spawn(move || process.probe(&probe));
+            }
+            // A oneshot becomes ready when it exits successfully.
+            None if self.spec.kind == ProcessKind::Oneshot => {
+                let process = self.clone();
+                thread::spawn(move || process.time_out_oneshot());
+            }
+            None => self.set_readiness(Readiness::Ready),
+        }
+
+        let process = self.clone();
+        thread::spawn(move || process.supervise(child_exit_rx));
+        Ok(true)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn spec(&self) -> &ProcessSpec {
+        &self.spec
+    }
+
+    pub fn depends_on(&self, name: &str) -> bool {
+        self.spec
+            .depends_on
+            .iter()
+            .any(|dependency| dependency == name)
+    }
+
+    // Blocks until the process is ready, returning false if it exited or was
+    // stopped before that happened.
+    pub fn wait_ready(&self) -> bool {
+        let mut state = self.lock_state();
+        while state.readiness == Readiness::Pending {
+            state = self
+                .state_changed
+                .wait(state)
+                .expect("failed to wait on process state");
+        }
+        state.readiness == Readiness::Ready
+    }
+
+    // Blocks until the process has exited for good, i.e. it will not restart.
+    pub fn wait(&self) {
+        let mut state = self.lock_state();
+        while !state.is_done {
+            state = self
+                .state_changed
+                .wait(state)
+                .expect("failed to wait on process state");
+        }
+    }
+
+    // Returns false if there is no running child to signal.
+    pub fn send_signal(&self, signal: Signal) -> bool {
+        let state = self.lock_state();
+        if let Some(pid) = state.pid {
+            Event::new("signaled")
+                .process(&self.name)
+                .pid(pid)
+                .signal(signal)
+                .log(format_args!(
+                    "sending {} to {}",
+                    signal::name(signal),
+                    self.name
+                ));
+            kill_process(pid, signal, self.spec.kill_mode);
+        }
+        state.pid.is_some()
+    }
+
+    // Forwards a non-terminating signal, translated through signal_map.
+    pub fn forward(&self, signal: Signal) {
+        if let Some(signal) = map_signal(&self.spec, signal) {
+            self.send_signal(signal);
+        }
+    }
+
+    // Unlike send_signal, this also cancels any pending restart, and a
+    // process that has not been started yet never will be.
+    pub fn stop(self: &Arc<Self>, signal: Signal) {
+        // Neither a process that was never started nor a held one that has
+        // already exited has a supervise thread left to report its exit.
+        let owes_exit = {
+            let mut state = self.lock_state();
+            let was_held = mem::replace(&mut state.is_held, false);
+            let owes_exit = (!state.is_started && !state.is_done) || (was_held && state.is_done);
+            if owes_exit {
+                state.is_stopping = true;
+                state.is_done = true;
+                state.readiness = Readiness::Failed;
+            }
+            owes_exit
+        };
+        if owes_exit {
+            self.state_changed.notify_all();
+            self.exit_tx
+                .send(Exit {
+                    name: self.name.clone(),
+                    code: None,
+                    is_failure: false,
+                    is_completed: false,
+                })
+                .expect("failed to send exit message in Process::stop");
+            return;
+        }
+        self.interrupt(signal);
+    }
+
+    // Stops the process without it counting as exited, so the rest of the
+    // container keeps running. It can be started again with resume.
+    pub fn hold(self: &Arc<Self>) -> Result<(), String> {
+        {
+            let mut state = self.lock_state();
+            if !state.is_started || state.is_stopping || state.is_done {
+                return Err(format!("{} is not running", self.name));
+            }
+            state.is_held = true;
+        }
+        self.interrupt(libc::SIGTERM);
+        Ok(())
+    }
+
+    // Starts a held process again once it has exited.
+    pub fn resume(self: &Arc<Self>) -> Result<(), String> {
+        {
+            let mut state = self.lock_state();
+            if !state.is_held || !state.is_done {
+                return Err(format!("{} is not stopped", self.name));
+            }
+            state.is_held = false;
+            state.is_stopping = false;
+            state.is_done = false;
+            state.readiness = Readiness::Pending;
+            while self.stop_rx.try_recv().is_ok() {}
+        }
+        self.state_changed.notify_all();
+
+        let result = self.launch();
+        if let Ok(true) = result {
+            return Ok(());
+        }
+        // Either the spawn failed or the container began shutting down in
+        // the meantime, in which case nobody else will report the exit.
+        let is_shutting_down = {
+            let mut state = self.lock_state();
+            state.is_done = true;
+            state.readiness = Readiness::Failed;
+            state.is_held = !state.is_stopping;
+            state.is_stopping
+        };
+        self.state_changed.notify_all();
+        if is_shutting_down {
+            self.exit_tx
+                .send(Exit {
+                    name: self.name.clone(),
+                    code: None,
+                    is_failure: false,
+                    is_completed: false,
+                })
+                .expect("failed to send exit message in Process::resume");
+        }
+        match result {
+            Err(source) => Err(self.spawn_error(source).to_string()),
+            Ok(_) => Err(format!("not starting {}: shutting down", self.name)),
+        }
+    }
+
+    // Stops the process for good without it counting as exited, so that a
+    // reload can remove or replace it. Returns false if it had already
+    // exited or is being shut down, in which case its exit is reported as
+    // usual.
+    pub fn retire(self: &Arc<Self>) -> bool {
+        let is_held = self.lock_state().is_held;
+        if !is_held && self.hold().is_err() {
+            return false;
+        }
+        self.wait();
+        mem::replace(&mut self.lock_state().is_held, false)
+    }
+
+    // Whether this is a oneshot that has run to completion. Its exit has
+    // already been reported.
+    pub fn is_completed(&self) -> bool {
+        self.is_completed_locked(&self.lock_state())
+    }
+
+    fn is_completed_locked(&self, state: &State) -> bool {
+        self.spec.kind == ProcessKind::Oneshot
+            && state.is_done
+            && !state.is_held
+            && state.readiness == Readiness::Ready
+    }
+
+    // What the control socket reports, along with the current pid.
+    pub fn status(&self) -> (&'static str, Option<u32>) {
+        let state = self.lock_state();
+        let status = if state.is_done && state.is_held {
+            "stopped"
+        } else if self.is_completed_locked(&state) {
+            "completed"
+        } else if state.is_done {
+            "exited"
+        } else if !state.is_started {
+            "pending"
+        } else if state.is_stopping {
+            "stopping"
+        } else if state.pid.is_none() {
+            "restarting"
+        } else if state.readiness == Readiness::Pending {
+            "starting"
+        } else {
+            "running"
+        };
+        (status, state.pid)
+    }
+
+    // Cancels any pending restart and shuts the running child down.
+    fn interrupt(self: &Arc<Self>, signal: Signal) {
+        let was_stopping = mem::replace(&mut self.lock_state().is_stopping, true);
+        let _ = self.stop_tx.try_send(());
+        let signal = self.spec.stop_signal.unwrap_or(signal);
+        if was_stopping {
+            self.send_signal(signal);
+            return;
+        }
+        let process = self.clone();
+        thread::spawn(move || process.shut_down(signal));
+    }
+
+    // Runs the stop_command if there is one, falling back to the stop signal
+    // if it fails. Whatever is still running once stop_timeout has passed is
+    // sent SIGKILL.
+    fn shut_down(&self, signal: Signal) {
+        let stop_timeout = Duration::from_secs_f64(
+            self.spec
+                .stop_timeout
+                .expect("stop_timeout is set when reading the config"),
+        );
+        let deadline = Instant::now() + stop_timeout;
+        let is_running = self.lock_state().pid.is_some();
+        match &self.spec.stop_command {
+            Some(stop_command) if is_running => {
+                if !self.run_stop_command(stop_command, stop_timeout) {
+                    self.send_signal(signal);
                 }
             }
-            if let Ok(()) = local_exit_rx.try_recv() {
+            _ => {
+                self.send_signal(signal);
+            }
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let (state, _) = self
+            .state_changed
+            .wait_timeout_while(self.lock_state(), remaining, |state| state.pid.is_some())
+            .expect("failed to wait on process state");
+        if let Some(pid) = state.pid {
+            Event::new("signaled")
+                .process(&self.name)
+                .pid(pid)
+                .signal(SIGKILL)
+                .log(format_args!(
+                    "{} did not exit within {:.1}s, sending SIGKILL",
+                    self.name,
+                    stop_timeout.as_secs_f64()
+                ));
+            kill_process(pid, SIGKILL, self.spec.kill_mode);
+        }
+    }
+
+    fn run_stop_command(&self, stop_command: &CommandLine, timeout: Duration) -> bool {
+        Event::new("stop_command")
+            .process(&self.name)
+            .log(format_args!(
+                "running stop command for {}: {}",
+                self.name, stop_command
+            ));
+        let event = Event::new("stop_command_failed").process(&self.name);
+        let result = command::build_with(stop_command, &self.spec)
+            .and_then(|mut command| reaper::run(&mut command, timeout));
+        match result {
+            Ok(Some(exit_status)) if exit_status.success() => true,
+            Ok(Some(exit_status)) => {
+                event.exit_status(exit_status).log(format_args!(
+                    "stop command for {} exited with: {}",
+                    self.name, exit_status
+                ));
+                false
+            }
+            Ok(None) => {
+                event.log(format_args!("stop command for {} timed out", self.name));
+                false
+            }
+            Err(err) => {
+                event.log(format_args!(
+                    "failed to run stop command for {}: {}",
+                    self.name, err
+                ));
+                false
+            }
+        }
+    }
+
+    fn supervise(&self, mut child_exit_rx: Receiver<ExitStatus>) {
+        let mut backoff = Backoff::new(&self.spec);
+        let mut exit = Exit {
+            name: self.name.clone(),
+            code: None,
+            is_failure: false,
+            is_completed: false,
+        };
+        loop {
+            let exit_status = child_exit_rx
+                .recv()
+                .unwrap_or_else(|_| panic!("failed to wait on {}", self.name));
+            let (pid, is_stopping, never_became_ready) = {
+                let mut state = self.lock_state();
+                (
+                    state.pid.take(),
+                    state.is_stopping,
+                    state.readiness == Readiness::Failed,
+                )
+            };
+            self.state_changed.notify_all();
+            let mut event = Event::new("exited").process(&self.name);
+            if let Some(pid) = pid {
+                event = event.pid(pid);
+            }
+            event
+                .exit_status(exit_status)
+                .log(format_args!("{} exited with: {}", self.name, exit_status));
+            exit.code = Some(exit_code(exit_status));
+            exit.is_failure = (!exit_status.success() && !is_stopping) || never_became_ready;
+            exit.is_completed = self.spec.kind == ProcessKind::Oneshot
+                && exit_status.success()
+                && !never_became_ready;
+            if is_stopping || !backoff.should_restart(exit_status) {
                 break;
             }
-        });
 
-        let is_alive_clone = is_alive.clone();
-        let name_clone = name.clone();
-        thread::spawn(move || {
-            let exit_status = child
-                .wait()
-                .expect(&format!("failed to wait on {}", name_clone));
-            is_alive_clone.store(false, Ordering::Relaxed);
-            println!("{} exited with: {}", name_clone, exit_status);
-            exit_tx
-                .send(())
-                .expect("failed to send exit message in Process::new");
-            local_exit_tx
-                .send(())
-                .expect("failed to send local exit message in Process::new");
+            let delay = match backoff.next_delay() {
+                Some(delay) => delay,
+                None => {
+                    Event::new("gave_up")
+                        .process(&self.name)
+                        .log(format_args!("{} restarted too often, giving up", self.name));
+                    break;
+                }
+            };
+            Event::new("restarting")
+                .process(&self.name)
+                .number("delay_ms", delay.as_millis() as i64)
+                .log(format_args!(
+                    "restarting {} in {:.1}s",
+                    self.name,
+                    delay.as_secs_f64()
+                ));
+            // A crash that was going to be restarted from is no more a
+            // failure than one that already was, and nothing was running to
+            // stop.
+            if self.stop_rx.recv_timeout(delay) != Err(RecvTimeoutError::Timeout) {
+                exit.code = None;
+                exit.is_failure = false;
+                break;
+            }
+
+            match self.spawn() {
+                Ok(Some(exit_rx)) => child_exit_rx = exit_rx,
+                Ok(None) => break,
+                Err(source) => {
+                    let err = self.spawn_error(source);
+                    Event::new("spawn_failed")
+                        .process(&self.name)
+                        .log(format_args!("{}", err));
+                    exit.code = Some(err.exit_code());
+                    exit.is_failure = true;
+                    break;
+                }
+            }
+        }
+
+        if exit.is_completed {
+            Event::new("completed")
+                .process(&self.name)
+                .log(format_args!("{} completed", self.name));
+        }
+        let is_held = {
+            let mut state = self.lock_state();
+            state.is_done = true;
+            if state.readiness == Readiness::Pending {
+                state.readiness = if exit.is_completed {
+                    Readiness::Ready
+                } else {
+                    Readiness::Failed
+                };
+            }
+            state.is_held
+        };
+        self.state_changed.notify_all();
+        if is_held {
+            return;
+        }
+        self.exit_tx
+            .send(exit)
+            .expect("failed to send exit message in Process::supervise");
+    }
+
+    // Polls the probe until it passes or the process goes away. If
+    // start_timeout runs out first the process is stopped, which in turn
+    // tears down the container.
+    fn probe(self: &Arc<Self>, probe: &Probe) {
+        let start_timeout = Duration::from_secs_f64(self.spec.start_timeout);
+        let deadline = Instant::now() + start_timeout;
+        loop {
+            {
+                let state = self.lock_state();
+                if state.is_stopping || state.is_done {
+                    return;
+                }
+            }
+            if probe::check(probe, &self.spec) {
+                Event::new("ready")
+                    .process(&self.name)
+                    .log(format_args!("{} is ready", self.name));
+                self.set_readiness(Readiness::Ready);
+                return;
+            }
+            if Instant::now() >= deadline {
+                Event::new("not_ready")
+                    .process(&self.name)
+                    .log(format_args!(
+                        "{} did not become ready within {:.1}s",
+                        self.name,
+                        start_timeout.as_secs_f64()
+                    ));
+                self.set_readiness(Readiness::Failed);
+                self.stop(libc::SIGTERM);
+                return;
+            }
+            thread::sleep(Duration::from_secs_f64(probe.interval));
+        }
+    }
+
+    // The oneshot counterpart of a probe running out of start_timeout: one
+    // that has not completed by then is stopped, failing startup.
+    fn time_out_oneshot(self: &Arc<Self>) {
+        let start_timeout = Duration::from_secs_f64(self.spec.start_timeout);
+        let (mut state, result) = self
+            .state_changed
+            .wait_timeout_while(self.lock_state(), start_timeout, |state| {
+                state.readiness == Readiness::Pending && !state.is_stopping
+            })
+            .expect("failed to wait on process state");
+        if !result.timed_out() {
+            return;
+        }
+        state.readiness = Readiness::Failed;
+        drop(state);
+        self.state_changed.notify_all();
+        Event::new("not_ready")
+            .process(&self.name)
+            .log(format_args!(
+                "{} did not complete within {:.1}s",
+                self.name,
+                start_timeout.as_secs_f64()
+            ));
+        self.stop(libc::SIGTERM);
+    }
+
+    // Spawning happens under the state lock so a concurrent stop either sees
+    // the new pid or prevents the spawn entirely.
+    fn spawn(&self) -> io::Result<Option<Receiver<ExitStatus>>> {
+        let mut state = self.lock_state();
+        if state.is_stopping {
+            return Ok(None);
+        }
+        if state.log_file.is_none() {
+            state.log_file = open_log_file(&self.spec)?;
+        }
+        let event = if state.is_started {
+            "restarted"
+        } else {
+            "spawned"
+        };
+        let (pid, exit_rx) = spawn_child(&self.name, &self.spec, &state.log_file, event)?;
+        state.pid = Some(pid);
+        state.is_started = true;
+        Ok(Some(exit_rx))
+    }
+
+    fn spawn_error(&self, source: io::Error) -> Error {
+        Error::Spawn {
+            name: self.name.clone(),
+            command: self.spec.command.clone(),
+            source,
+        }
+    }
+
+    fn set_readiness(&self, readiness: Readiness) {
+        self.lock_state().readiness = readiness;
+        self.state_changed.notify_all();
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("failed to lock process state")
+    }
+}
+
+pub fn open_log_file(spec: &ProcessSpec) -> io::Result<Option<Arc<Mutex<LogFile>>>> {
+    match &spec.log_file {
+        Some(path) => {
+            let log_file = LogFile::open(
+                path,
+                spec.log_max_size,
+                spec.log_max_files,
+                spec.log_compress,
+            )?;
+            Ok(Some(Arc::new(Mutex::new(log_file))))
+        }
+        None => Ok(None),
+    }
+}
+
+// Spawns the command of a process or job and captures its output under
+// `name`. The event is logged as soon as the pid is known, so that it comes
+// before anything the child prints.
+pub fn spawn_child(
+    name: &str,
+    spec: &ProcessSpec,
+    log_file: &Option<Arc<Mutex<LogFile>>>,
+    event: &'static str,
+) -> io::Result<(u32, Receiver<ExitStatus>)> {
+    let mut command = command::build(spec)?;
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    // A session of its own makes the pid a process group id for
+    // kill_mode = "group", and keeps terminal signals away from the child.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
         });
+    }
+    let (mut child, exit_rx) = reaper::spawn(&mut command)?;
+    Event::new(event)
+        .process(name)
+        .pid(child.id())
+        .log(format_args!("{} started with pid {}", name, child.id()));
+    let to_console = log_file.is_none() || spec.log_tee;
+    if let Some(stdout) = child.stdout.take() {
+        let destination = Destination {
+            stream: Some(Stream::Stdout).filter(|_| to_console),
+            log_file: log_file.clone(),
+        };
+        output::capture(name, stdout, destination);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let destination = Destination {
+            stream: Some(Stream::Stderr).filter(|_| to_console),
+            log_file: log_file.clone(),
+        };
+        output::capture(name, stderr, destination);
+    }
+    Ok((child.id(), exit_rx))
+}
 
-        Self {
-            name,
-            pid,
-            signal_tx,
-            is_alive,
+// Deaths by signal follow the shell's 128 + signal convention.
+fn exit_code(exit_status: ExitStatus) -> i32 {
+    match exit_status.code() {
+        Some(code) => code,
+        None => 128 + exit_status.signal().unwrap_or(0),
+    }
+}
+
+// What a forwarded signal becomes according to signal_map, if anything.
+pub fn map_signal(spec: &ProcessSpec, signal: Signal) -> Option<Signal> {
+    match spec.signal_map.get(&signal) {
+        Some(forward_as) => *forward_as,
+        None => Some(signal),
+    }
+}
+
+pub fn kill_process(pid: u32, signal: Signal, kill_mode: KillMode) {
+    let pids = match kill_mode {
+        KillMode::Process => vec![pid as i32],
+        KillMode::Group => vec![-(pid as i32)],
+        KillMode::Tree => {
+            let mut pids = vec![pid as i32];
+            pids.extend(descendants(pid).into_iter().map(|pid| pid as i32));
+            pids
+        }
+    };
+    for pid in pids {
+        unsafe {
+            kill(pid, signal);
         }
     }
+}
 
-    pub fn send_signal(&self, signal: Signal) {
-        if self.is_alive.load(Ordering::Relaxed) {
-            self.signal_tx
-                .send(signal)
-                .expect("failed to send signal message in Process::send_signal");
+// Anything that was orphaned and reparented to spot-init is no longer below
+// the process and will not be found.
+fn descendants(pid: u32) -> Vec<u32> {
+    let mut children = HashMap::<u32, Vec<u32>>::new();
+    for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let child = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(child) => child,
+            None => continue,
+        };
+        if let Some(parent) = parent_pid(child) {
+            children.entry(parent).or_default().push(child);
         }
     }
+
+    let mut descendants = Vec::new();
+    let mut queue = vec![pid];
+    while let Some(pid) = queue.pop() {
+        if let Some(pids) = children.get(&pid) {
+            descendants.extend(pids);
+            queue.extend(pids);
+        }
+    }
+    descendants
+}
+
+// The command name in /proc/<pid>/stat may itself contain spaces and
+// parentheses, so fields are counted from the last `)`.
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    stat[stat.rfind(')')? + 1..]
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
 }
@@ -0,0 +1,99 @@
+use crate::config::{Config, ProcessSpec};
+use crate::envfile;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Reads a Heroku-style Procfile, one `name: command` per line. A `.env`
+// next to it sets the environment of every process.
+pub fn parse(procfile_path: &Path, contents: &str) -> Result<Config, String> {
+    let env_path = procfile_path.with_file_name(".env");
+    let env = match fs::read_to_string(&env_path) {
+        Ok(contents) => {
+            envfile::parse(&contents).map_err(|err| format!("{}: {}", env_path.display(), err))?
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Default::default(),
+        Err(err) => return Err(format!("failed to read {}: {}", env_path.display(), err)),
+    };
+
+    let mut processes = BTreeMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, command) = line
+            .split_once(':')
+            .map(|(name, command)| (name.trim(), command.trim()))
+            .filter(|(name, command)| is_valid_name(name) && !command.is_empty())
+            .ok_or_else(|| format!("line {}: expected `name: command`", index + 1))?;
+        let mut spec = ProcessSpec::from_command(command);
+        spec.env = env.clone();
+        if processes.insert(name.to_owned(), spec).is_some() {
+            return Err(format!("line {}: duplicate process {}", index + 1, name));
+        }
+    }
+    Ok(Config::from_processes(processes))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommandLine;
+
+    // Nothing sits next to this path, so no .env is read.
+    fn parse_procfile(contents: &str) -> Result<Config, String> {
+        parse(Path::new("/nonexistent/Procfile"), contents)
+    }
+
+    #[test]
+    fn parses_processes() {
+        let config =
+            parse_procfile("# services\n\nweb: bundle exec rails s -p $PORT\n  worker:  sidekiq\n")
+                .unwrap();
+        let commands: Vec<_> = config
+            .processes
+            .iter()
+            .map(|(name, spec)| (name.as_str(), spec.command.clone()))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                (
+                    "web",
+                    CommandLine::Shell("bundle exec rails s -p $PORT".to_owned())
+                ),
+                ("worker", CommandLine::Shell("sidekiq".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let invalid = ["web", "web:", "my web: app", ": app", "web.1: app"];
+        for line in invalid {
+            assert_eq!(
+                parse_procfile(line).unwrap_err(),
+                "line 1: expected `name: command`",
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        assert_eq!(
+            parse_procfile("web: a\nworker: b\nweb: c\n").unwrap_err(),
+            "line 3: duplicate process web"
+        );
+    }
+}
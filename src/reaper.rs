@@ -0,0 +1,72 @@
+use crate::event::Event;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use libc::{kill, waitpid, SIGKILL, WNOHANG};
+use signal_hook::iterator::Signals;
+use std::collections::BTreeMap;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Children spawned by spot-init itself, keyed by pid. Anything else that
+// gets reaped is an orphan that was reparented to us.
+static WATCHED: Mutex<BTreeMap<u32, Sender<ExitStatus>>> = Mutex::new(BTreeMap::new());
+
+pub fn start() -> io::Result<()> {
+    let signals = Signals::new([signal_hook::SIGCHLD])?;
+
+    thread::spawn(move || {
+        reap();
+        signals.forever().for_each(|_| reap());
+    });
+    Ok(())
+}
+
+// The lock is held across spawn so the reaper can never collect a child
+// before its exit channel has been registered.
+pub fn spawn(command: &mut Command) -> io::Result<(Child, Receiver<ExitStatus>)> {
+    let mut watched = WATCHED.lock().expect("failed to lock reaper registry");
+    let child = command.spawn()?;
+    let (exit_tx, exit_rx) = bounded::<ExitStatus>(1);
+    watched.insert(child.id(), exit_tx);
+    Ok((child, exit_rx))
+}
+
+// Runs a short-lived command to completion, killing it once the timeout is
+// exceeded, in which case None is returned.
+pub fn run(command: &mut Command, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let (child, exit_rx) = spawn(command)?;
+    match exit_rx.recv_timeout(timeout) {
+        Ok(exit_status) => Ok(Some(exit_status)),
+        Err(_) => {
+            unsafe {
+                kill(child.id() as i32, SIGKILL);
+            }
+            let _ = exit_rx.recv();
+            Ok(None)
+        }
+    }
+}
+
+fn reap() {
+    let mut watched = WATCHED.lock().expect("failed to lock reaper registry");
+    loop {
+        let mut status = 0;
+        let pid = unsafe { waitpid(-1, &mut status, WNOHANG) };
+        if pid <= 0 {
+            break;
+        }
+        let exit_status = ExitStatus::from_raw(status);
+        match watched.remove(&(pid as u32)) {
+            Some(exit_tx) => {
+                let _ = exit_tx.send(exit_status);
+            }
+            None => Event::new("reaped")
+                .pid(pid as u32)
+                .exit_status(exit_status)
+                .log(format_args!("reaped orphan {} with: {}", pid, exit_status)),
+        }
+    }
+}
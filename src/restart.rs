@@ -0,0 +1,139 @@
+use crate::config::{ProcessSpec, RestartPolicy};
+use std::collections::VecDeque;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const BACKOFF_MULTIPLIER: f64 = 2.0;
+const BACKOFF_JITTER: f64 = 0.2;
+
+#[derive(Debug)]
+pub struct Backoff {
+    policy: RestartPolicy,
+    delay: f64,
+    delay_max: f64,
+    max_restarts: usize,
+    window: Duration,
+    restarts: VecDeque<Instant>,
+    seed: u64,
+}
+
+impl Backoff {
+    pub fn new(spec: &ProcessSpec) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos() as u64)
+            .unwrap_or(0);
+
+        Self {
+            policy: spec.restart,
+            delay: spec.restart_delay,
+            delay_max: spec.restart_delay_max,
+            max_restarts: spec.max_restarts,
+            window: Duration::from_secs_f64(spec.restart_window),
+            restarts: VecDeque::new(),
+            seed: (nanos ^ (u64::from(std::process::id()) << 32)) | 1,
+        }
+    }
+
+    pub fn should_restart(&self, exit_status: ExitStatus) -> bool {
+        match self.policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !exit_status.success(),
+            RestartPolicy::Always => true,
+        }
+    }
+
+    // Returns None once max_restarts have happened within the window. The
+    // exponent is the number of recent restarts, so a process that stays up
+    // for a full window starts over at the initial delay.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        while let Some(&restarted_at) = self.restarts.front() {
+            if now.duration_since(restarted_at) < self.window {
+                break;
+            }
+            self.restarts.pop_front();
+        }
+        if self.restarts.len() >= self.max_restarts {
+            return None;
+        }
+
+        let delay =
+            (self.delay * BACKOFF_MULTIPLIER.powi(self.restarts.len() as i32)).min(self.delay_max);
+        let jitter = 1.0 + BACKOFF_JITTER * (2.0 * self.random() - 1.0);
+        self.restarts.push_back(now);
+        Some(Duration::from_secs_f64((delay * jitter).max(0.0)))
+    }
+
+    // xorshift64, good enough to keep restarts of sibling processes apart
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(max_restarts: usize, restart_window: f64) -> Backoff {
+        let mut spec = ProcessSpec::from_command("true");
+        spec.restart_delay = 1.0;
+        spec.restart_delay_max = 10.0;
+        spec.max_restarts = max_restarts;
+        spec.restart_window = restart_window;
+        Backoff::new(&spec)
+    }
+
+    fn assert_jittered(delay: Duration, nominal: f64) {
+        let seconds = delay.as_secs_f64();
+        assert!(
+            seconds >= nominal * (1.0 - BACKOFF_JITTER)
+                && seconds <= nominal * (1.0 + BACKOFF_JITTER),
+            "{} is not within 20% of {}",
+            seconds,
+            nominal
+        );
+    }
+
+    #[test]
+    fn doubles_the_delay_up_to_the_maximum() {
+        let mut backoff = backoff(100, 1e6);
+        for nominal in [1.0, 2.0, 4.0, 8.0, 10.0, 10.0, 10.0] {
+            assert_jittered(backoff.next_delay().unwrap(), nominal);
+        }
+    }
+
+    #[test]
+    fn keeps_jitter_within_bounds() {
+        let mut backoff = backoff(usize::MAX, 0.0);
+        let delays: Vec<_> = (0..1000)
+            .map(|_| backoff.next_delay().unwrap().as_secs_f64())
+            .collect();
+        for &delay in &delays {
+            assert_jittered(Duration::from_secs_f64(delay), 1.0);
+        }
+        // Sibling processes should not all restart at the same moment.
+        assert!(delays.iter().any(|&delay| delay != delays[0]));
+    }
+
+    #[test]
+    fn gives_up_after_max_restarts_in_the_window() {
+        let mut backoff = backoff(3, 1e6);
+        for _ in 0..3 {
+            assert!(backoff.next_delay().is_some());
+        }
+        assert_eq!(backoff.next_delay(), None);
+        assert_eq!(backoff.next_delay(), None);
+    }
+
+    #[test]
+    fn forgets_restarts_outside_the_window() {
+        let mut backoff = backoff(3, 0.0);
+        for _ in 0..10 {
+            assert_jittered(backoff.next_delay().unwrap(), 1.0);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::parse_size;
+use std::fs;
+use std::io;
+use std::mem;
+
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+// Every limit setrlimit knows about, and whether it is in bytes.
+const RESOURCES: &[(&str, Resource, bool)] = &[
+    ("as", libc::RLIMIT_AS, true),
+    ("core", libc::RLIMIT_CORE, true),
+    ("cpu", libc::RLIMIT_CPU, false),
+    ("data", libc::RLIMIT_DATA, true),
+    ("fsize", libc::RLIMIT_FSIZE, true),
+    ("locks", libc::RLIMIT_LOCKS, false),
+    ("memlock", libc::RLIMIT_MEMLOCK, true),
+    ("msgqueue", libc::RLIMIT_MSGQUEUE, true),
+    ("nice", libc::RLIMIT_NICE, false),
+    ("nofile", libc::RLIMIT_NOFILE, false),
+    ("nproc", libc::RLIMIT_NPROC, false),
+    ("rss", libc::RLIMIT_RSS, true),
+    ("rtprio", libc::RLIMIT_RTPRIO, false),
+    ("rttime", libc::RLIMIT_RTTIME, false),
+    ("sigpending", libc::RLIMIT_SIGPENDING, false),
+    ("stack", libc::RLIMIT_STACK, true),
+];
+
+const CAP_SYS_RESOURCE: u32 = 24;
+
+// A resource limit for a process, set as both its soft and hard limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rlimit {
+    pub name: &'static str,
+    resource: Resource,
+    value: libc::rlim_t,
+}
+
+impl Rlimit {
+    // Values are a number, `unlimited`, or for limits in bytes a size with a
+    // K, M, G or T suffix.
+    pub fn parse(name: &str, value: &str) -> Result<Self, String> {
+        let &(name, resource, is_bytes) = RESOURCES
+            .iter()
+            .find(|(resource_name, _, _)| *resource_name == name)
+            .ok_or_else(|| {
+                let names = RESOURCES
+                    .iter()
+                    .map(|(name, _, _)| *name)
+                    .collect::<Vec<_>>();
+                format!(
+                    "unknown rlimit `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+        let value = match value {
+            "unlimited" | "infinity" => libc::RLIM_INFINITY,
+            _ if !is_bytes => value
+                .parse()
+                .map_err(|_| format!("invalid {} limit `{}`, expected a number", name, value))?,
+            _ => parse_size(value).ok_or_else(|| {
+                format!(
+                    "invalid {} limit `{}`, expected a size like 2G",
+                    name, value
+                )
+            })?,
+        };
+        Ok(Self {
+            name,
+            resource,
+            value,
+        })
+    }
+}
+
+// Checks in spot-init itself what would otherwise only fail in the child
+// with a bare EPERM: raising a hard limit takes CAP_SYS_RESOURCE, and no
+// file limit can go above fs.nr_open.
+pub fn check(rlimits: &[Rlimit]) -> io::Result<()> {
+    for rlimit in rlimits {
+        let hard = current_hard_limit(rlimit.resource)?;
+        if rlimit.value <= hard {
+            continue;
+        }
+        let cannot_raise = |reason: String| {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "cannot raise the hard {} limit from {} to {}: {}",
+                    rlimit.name,
+                    describe(hard),
+                    describe(rlimit.value),
+                    reason
+                ),
+            ))
+        };
+        if !has_capability(CAP_SYS_RESOURCE) {
+            return cannot_raise("spot-init lacks CAP_SYS_RESOURCE".to_owned());
+        }
+        if rlimit.resource == libc::RLIMIT_NOFILE {
+            if let Some(nr_open) = nr_open().filter(|nr_open| rlimit.value > *nr_open) {
+                return cannot_raise(format!("it is above fs.nr_open ({})", nr_open));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Runs in the child between fork and exec, so it must not allocate.
+pub fn apply(rlimits: &[Rlimit]) -> io::Result<()> {
+    for rlimit in rlimits {
+        let limit = libc::rlimit {
+            rlim_cur: rlimit.value,
+            rlim_max: rlimit.value,
+        };
+        if unsafe { libc::setrlimit(rlimit.resource, &limit) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn current_hard_limit(resource: Resource) -> io::Result<libc::rlim_t> {
+    let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+    if unsafe { libc::getrlimit(resource, &mut limit) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(limit.rlim_max)
+}
+
+fn has_capability(capability: u32) -> bool {
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+        .is_some_and(|mask| mask & 1 << capability != 0)
+}
+
+fn nr_open() -> Option<libc::rlim_t> {
+    fs::read_to_string("/proc/sys/fs/nr_open")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn describe(value: libc::rlim_t) -> String {
+    if value == libc::RLIM_INFINITY {
+        return "unlimited".to_owned();
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_an_unknown_name() {
+        let err = Rlimit::parse("files", "1024").unwrap_err();
+        assert!(err.starts_with("unknown rlimit `files`, expected one of as, core,"));
+    }
+
+    #[test]
+    fn parses_sizes_for_limits_in_bytes() {
+        let rlimit = Rlimit::parse("as", "2G").unwrap();
+        assert_eq!(rlimit.name, "as");
+        assert_eq!(rlimit.resource, libc::RLIMIT_AS);
+        assert_eq!(rlimit.value, 2 << 30);
+    }
+
+    #[test]
+    fn rejects_sizes_for_counts() {
+        assert_eq!(
+            Rlimit::parse("nofile", "2G").unwrap_err(),
+            "invalid nofile limit `2G`, expected a number"
+        );
+        assert_eq!(Rlimit::parse("nofile", "65536").unwrap().value, 65536);
+    }
+
+    #[test]
+    fn parses_unlimited() {
+        assert_eq!(
+            Rlimit::parse("core", "unlimited").unwrap().value,
+            libc::RLIM_INFINITY
+        );
+        assert_eq!(
+            Rlimit::parse("nproc", "infinity").unwrap().value,
+            libc::RLIM_INFINITY
+        );
+    }
+
+    #[test]
+    fn rejects_negative_values() {
+        assert_eq!(
+            Rlimit::parse("nofile", "-1").unwrap_err(),
+            "invalid nofile limit `-1`, expected a number"
+        );
+        assert_eq!(
+            Rlimit::parse("stack", "-8M").unwrap_err(),
+            "invalid stack limit `-8M`, expected a size like 2G"
+        );
+    }
+}
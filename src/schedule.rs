@@ -0,0 +1,343 @@
+use std::mem;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+// The most days each month can have, counting leap years.
+const MONTH_LENGTHS: &[u32] = &[31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+// Gives up looking for the next run after this many steps, which covers
+// several years of even the sparsest schedule that can match at all.
+const MAX_STEPS: usize = 100_000;
+
+// A cron schedule in local time: minute, hour, day of month, month and day
+// of week, each a `*`, a number or name, a range `a-b`, any of those with a
+// step `/n`, or a comma separated list of them. `@yearly`, `@monthly`,
+// `@weekly`, `@daily` and `@hourly` are accepted as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Like cron, a run is due on either day field if both are restricted.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(format!(
+                "expected 5 fields in schedule `{}`, found {}",
+                expression,
+                fields.len()
+            ));
+        }
+        let weekdays = parse_field(fields[4], 0, 7, WEEKDAYS)?;
+        let schedule = Self {
+            minutes: parse_field(fields[0], 0, 59, &[])?,
+            hours: parse_field(fields[1], 0, 23, &[])?,
+            days: parse_field(fields[2], 1, 31, &[])?,
+            months: parse_field(fields[3], 1, 12, MONTHS)?,
+            // 7 is Sunday as well as 0.
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            days_restricted: !fields[2].starts_with('*'),
+            weekdays_restricted: !fields[4].starts_with('*'),
+        };
+        if !schedule.has_possible_day() {
+            return Err(format!("schedule `{}` never matches a date", expression));
+        }
+        Ok(schedule)
+    }
+
+    // Whether one of the days of the month exists in one of the months, as
+    // in `0 0 31 2 *` it never does. Restricted weekdays always match some
+    // day instead.
+    fn has_possible_day(&self) -> bool {
+        if !self.days_restricted || self.weekdays_restricted {
+            return true;
+        }
+        MONTH_LENGTHS.iter().zip(1..).any(|(length, month)| {
+            has(self.months, month) && (1..=*length as libc::c_int).any(|day| has(self.days, day))
+        })
+    }
+
+    // The first whole minute after `after` that the schedule matches.
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let after = after.duration_since(UNIX_EPOCH).ok()?.as_secs() as libc::time_t;
+        let mut time = after - after % 60 + 60;
+        for _ in 0..MAX_STEPS {
+            let mut tm = local_time(time)?;
+            if !has(self.months, tm.tm_mon + 1) {
+                tm.tm_mon += 1;
+                tm.tm_mday = 1;
+                tm.tm_hour = 0;
+                tm.tm_min = 0;
+            } else if !self.matches_day(&tm) {
+                tm.tm_mday += 1;
+                tm.tm_hour = 0;
+                tm.tm_min = 0;
+            } else if !has(self.hours, tm.tm_hour) {
+                tm.tm_hour += 1;
+                tm.tm_min = 0;
+            } else if !has(self.minutes, tm.tm_min) {
+                tm.tm_min += 1;
+            } else {
+                return Some(UNIX_EPOCH + Duration::from_secs(time as u64));
+            }
+            tm.tm_sec = 0;
+            tm.tm_isdst = -1;
+            // mktime normalizes the overflowed field. Around a DST change it
+            // may land on the same time again, which must not loop forever.
+            time = unsafe { libc::mktime(&mut tm) }.max(time + 60);
+        }
+        None
+    }
+
+    fn matches_day(&self, tm: &libc::tm) -> bool {
+        let day = has(self.days, tm.tm_mday);
+        let weekday = has(self.weekdays, tm.tm_wday);
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+fn has(set: u64, value: libc::c_int) -> bool {
+    (0..64).contains(&value) && set & 1 << value != 0
+}
+
+fn local_time(time: libc::time_t) -> Option<libc::tm> {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+    Some(tm)
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid step in `{}`", item))?;
+                (range, step)
+            }
+            None => (item, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (
+                parse_value(start, min, max, names)?,
+                parse_value(end, min, max, names)?,
+            ),
+            // `5/15` runs from 5 to the end of the range.
+            None if step > 1 => (parse_value(range, min, max, names)?, max),
+            None => {
+                let value = parse_value(range, min, max, names)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return Err(format!("invalid range `{}`", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+fn parse_value(value: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, String> {
+    let lowercase = value.to_lowercase();
+    if let Some(index) = names.iter().position(|name| *name == lowercase) {
+        return Ok(min + index as u32);
+    }
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|value| (min..=max).contains(value))
+        .ok_or_else(|| format!("`{}` is not between {} and {}", value, min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Times are local, like the schedules themselves. The dates used stay
+    // clear of the usual daylight saving changes.
+    fn local(year: i32, month: i32, day: i32, hour: i32, minute: i32) -> SystemTime {
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        tm.tm_year = year - 1900;
+        tm.tm_mon = month - 1;
+        tm.tm_mday = day;
+        tm.tm_hour = hour;
+        tm.tm_min = minute;
+        tm.tm_isdst = -1;
+        let time = unsafe { libc::mktime(&mut tm) };
+        UNIX_EPOCH + Duration::from_secs(time as u64)
+    }
+
+    fn next(expression: &str, after: SystemTime) -> Option<SystemTime> {
+        Schedule::parse(expression).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn every_minute() {
+        let after = local(2025, 1, 15, 10, 30) + Duration::from_secs(17);
+        assert_eq!(next("* * * * *", after), Some(local(2025, 1, 15, 10, 31)));
+        // A run is never due at the exact time asked about.
+        let after = local(2025, 1, 15, 10, 30);
+        assert_eq!(next("* * * * *", after), Some(local(2025, 1, 15, 10, 31)));
+    }
+
+    #[test]
+    fn steps() {
+        let after = local(2025, 1, 15, 10, 31);
+        assert_eq!(
+            next("*/15 * * * *", after),
+            Some(local(2025, 1, 15, 10, 45))
+        );
+        assert_eq!(
+            next("5/20 * * * *", after),
+            Some(local(2025, 1, 15, 10, 45))
+        );
+        assert_eq!(next("0 */6 * * *", after), Some(local(2025, 1, 15, 12, 0)));
+    }
+
+    #[test]
+    fn ranges_and_lists() {
+        let after = local(2025, 1, 15, 18, 0);
+        assert_eq!(next("0 9-17 * * *", after), Some(local(2025, 1, 16, 9, 0)));
+        assert_eq!(
+            next("0 8-20/4 * * *", after),
+            Some(local(2025, 1, 15, 20, 0))
+        );
+        assert_eq!(
+            next("0 6,19,22 * * *", after),
+            Some(local(2025, 1, 15, 19, 0))
+        );
+    }
+
+    #[test]
+    fn names() {
+        // 2025-01-15 is a Wednesday.
+        let after = local(2025, 1, 15, 12, 0);
+        assert_eq!(next("0 0 * * FRI", after), Some(local(2025, 1, 17, 0, 0)));
+        assert_eq!(
+            next("0 0 * * mon-tue", after),
+            Some(local(2025, 1, 20, 0, 0))
+        );
+        assert_eq!(next("0 0 1 Mar *", after), Some(local(2025, 3, 1, 0, 0)));
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        let after = local(2025, 1, 15, 12, 0);
+        let sunday = Some(local(2025, 1, 19, 0, 0));
+        assert_eq!(next("0 0 * * 7", after), sunday);
+        assert_eq!(next("0 0 * * 0", after), sunday);
+        assert_eq!(next("0 0 * * sun", after), sunday);
+        assert_eq!(next("0 0 * * 5-7", after), Some(local(2025, 1, 17, 0, 0)));
+    }
+
+    #[test]
+    fn either_day_field_matches_when_both_are_restricted() {
+        // The 20th, or any Friday.
+        let after = local(2025, 1, 15, 12, 0);
+        assert_eq!(next("0 0 20 * fri", after), Some(local(2025, 1, 17, 0, 0)));
+        let after = local(2025, 1, 17, 12, 0);
+        assert_eq!(next("0 0 20 * fri", after), Some(local(2025, 1, 20, 0, 0)));
+    }
+
+    #[test]
+    fn both_day_fields_match_when_one_is_a_wildcard() {
+        let after = local(2025, 1, 15, 12, 0);
+        assert_eq!(next("0 0 */2 * *", after), Some(local(2025, 1, 17, 0, 0)));
+        assert_eq!(next("0 0 * * 1", after), Some(local(2025, 1, 20, 0, 0)));
+    }
+
+    #[test]
+    fn rolls_over_months_and_years() {
+        assert_eq!(
+            next("0 0 31 * *", local(2025, 4, 1, 0, 0)),
+            Some(local(2025, 5, 31, 0, 0))
+        );
+        assert_eq!(
+            next("30 23 * * *", local(2025, 12, 31, 23, 30)),
+            Some(local(2026, 1, 1, 23, 30))
+        );
+        assert_eq!(
+            next("0 0 1 1 *", local(2025, 6, 1, 0, 0)),
+            Some(local(2026, 1, 1, 0, 0))
+        );
+    }
+
+    #[test]
+    fn leap_days() {
+        assert_eq!(
+            next("0 0 29 2 *", local(2025, 1, 1, 0, 0)),
+            Some(local(2028, 2, 29, 0, 0))
+        );
+    }
+
+    #[test]
+    fn shortcuts() {
+        let after = local(2025, 1, 15, 10, 30);
+        assert_eq!(next("@hourly", after), Some(local(2025, 1, 15, 11, 0)));
+        assert_eq!(next("@daily", after), Some(local(2025, 1, 16, 0, 0)));
+        assert_eq!(next("@weekly", after), Some(local(2025, 1, 19, 0, 0)));
+        assert_eq!(next("@monthly", after), Some(local(2025, 2, 1, 0, 0)));
+        assert_eq!(next("@yearly", after), Some(local(2026, 1, 1, 0, 0)));
+    }
+
+    #[test]
+    fn impossible_dates_are_rejected() {
+        assert_eq!(
+            Schedule::parse("0 0 31 2 *"),
+            Err("schedule `0 0 31 2 *` never matches a date".to_owned())
+        );
+        assert!(Schedule::parse("0 0 30,31 feb *").is_err());
+        assert!(Schedule::parse("0 0 31 4,6,9,11 *").is_err());
+        // Restricted weekdays match on other days of the month.
+        assert!(Schedule::parse("0 0 31 2 mon").is_ok());
+        assert!(Schedule::parse("0 0 29 2 *").is_ok());
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let error = |expression| Schedule::parse(expression).unwrap_err();
+        assert_eq!(
+            error("* * * *"),
+            "expected 5 fields in schedule `* * * *`, found 4"
+        );
+        assert_eq!(error("60 * * * *"), "`60` is not between 0 and 59");
+        assert_eq!(error("* 24 * * *"), "`24` is not between 0 and 23");
+        assert_eq!(error("* * 0 * *"), "`0` is not between 1 and 31");
+        assert_eq!(error("* * * 13 *"), "`13` is not between 1 and 12");
+        assert_eq!(error("* * * * 8"), "`8` is not between 0 and 7");
+        assert_eq!(error("* * * foo *"), "`foo` is not between 1 and 12");
+        assert_eq!(error("10-5 * * * *"), "invalid range `10-5`");
+        assert_eq!(error("*/0 * * * *"), "invalid step in `*/0`");
+        assert_eq!(error("*/x * * * *"), "invalid step in `*/x`");
+    }
+}
@@ -0,0 +1,76 @@
+pub type Signal = i32;
+
+const SIGNALS: &[(&str, Signal)] = &[
+    ("SIGHUP", libc::SIGHUP),
+    ("SIGINT", libc::SIGINT),
+    ("SIGQUIT", libc::SIGQUIT),
+    ("SIGILL", libc::SIGILL),
+    ("SIGTRAP", libc::SIGTRAP),
+    ("SIGABRT", libc::SIGABRT),
+    ("SIGBUS", libc::SIGBUS),
+    ("SIGFPE", libc::SIGFPE),
+    ("SIGKILL", libc::SIGKILL),
+    ("SIGUSR1", libc::SIGUSR1),
+    ("SIGSEGV", libc::SIGSEGV),
+    ("SIGUSR2", libc::SIGUSR2),
+    ("SIGPIPE", libc::SIGPIPE),
+    ("SIGALRM", libc::SIGALRM),
+    ("SIGTERM", libc::SIGTERM),
+    ("SIGCHLD", libc::SIGCHLD),
+    ("SIGCONT", libc::SIGCONT),
+    ("SIGSTOP", libc::SIGSTOP),
+    ("SIGTSTP", libc::SIGTSTP),
+    ("SIGTTIN", libc::SIGTTIN),
+    ("SIGTTOU", libc::SIGTTOU),
+    ("SIGURG", libc::SIGURG),
+    ("SIGXCPU", libc::SIGXCPU),
+    ("SIGXFSZ", libc::SIGXFSZ),
+    ("SIGVTALRM", libc::SIGVTALRM),
+    ("SIGPROF", libc::SIGPROF),
+    ("SIGWINCH", libc::SIGWINCH),
+    ("SIGIO", libc::SIGIO),
+    ("SIGPWR", libc::SIGPWR),
+    ("SIGSYS", libc::SIGSYS),
+];
+
+// Everything spot-init forwards to its children. Only the terminating ones
+// shut the container down.
+pub const FORWARDED: &[Signal] = &[
+    libc::SIGTERM,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGHUP,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+    libc::SIGWINCH,
+    libc::SIGALRM,
+    libc::SIGCONT,
+    libc::SIGPWR,
+];
+
+pub fn is_terminating(signal: Signal) -> bool {
+    signal == libc::SIGTERM || signal == libc::SIGINT || signal == libc::SIGQUIT
+}
+
+// Accepts `SIGTERM`, `TERM` (in any case) or a signal number.
+pub fn parse(name: &str) -> Option<Signal> {
+    if let Ok(number) = name.parse::<Signal>() {
+        return SIGNALS
+            .iter()
+            .find(|(_, signal)| *signal == number)
+            .map(|(_, signal)| *signal);
+    }
+    let name = name.to_uppercase();
+    let name = name.trim_start_matches("SIG");
+    SIGNALS
+        .iter()
+        .find(|(signal_name, _)| &signal_name[3..] == name)
+        .map(|(_, signal)| *signal)
+}
+
+pub fn name(signal: Signal) -> &'static str {
+    SIGNALS
+        .iter()
+        .find(|(_, number)| *number == signal)
+        .map_or("SIGUNKNOWN", |(signal_name, _)| signal_name)
+}
@@ -0,0 +1,291 @@
+use crate::config::{load_config, Config, Format, ProcessSpec};
+use crate::event::Event;
+use crate::job::Job;
+use crate::process::{Exit, Process};
+use crate::signal::Signal;
+use crate::{CHILD_PROCESS_COUNT, IS_SIGNALED};
+use crossbeam_channel::Sender;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+// The set of supervised processes, in start order, and scheduled jobs, along
+// with how signals are routed to them. These only change when the config is
+// reloaded, so everything else works on a snapshot.
+pub struct Supervisor {
+    config_path: String,
+    format: Option<Format>,
+    processes: RwLock<Vec<Arc<Process>>>,
+    jobs: RwLock<Vec<Arc<Job>>>,
+    signal_routes: RwLock<HashMap<Signal, Vec<String>>>,
+    reload_signal: RwLock<Option<Signal>>,
+    exit_tx: Sender<Exit>,
+    is_started: AtomicBool,
+    reloading: Mutex<()>,
+}
+
+impl Supervisor {
+    pub fn new(
+        config_path: &str,
+        format: Option<Format>,
+        config: &Config,
+        start_order: &[String],
+        exit_tx: Sender<Exit>,
+    ) -> Self {
+        let mut processes = Vec::new();
+        let mut jobs = Vec::new();
+        for name in start_order {
+            CHILD_PROCESS_COUNT.fetch_add(1, Ordering::Relaxed);
+            let spec = config.processes[name].clone();
+            if spec.schedule.is_some() {
+                jobs.push(Arc::new(Job::new(name.clone(), spec, exit_tx.clone())));
+            } else {
+                processes.push(Arc::new(Process::new(name.clone(), spec, exit_tx.clone())));
+            }
+        }
+
+        Self {
+            config_path: config_path.to_owned(),
+            format,
+            processes: RwLock::new(processes),
+            jobs: RwLock::new(jobs),
+            signal_routes: RwLock::new(config.signals.clone()),
+            reload_signal: RwLock::new(config.reload_signal),
+            exit_tx,
+            is_started: AtomicBool::new(false),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    pub fn processes(&self) -> Vec<Arc<Process>> {
+        self.processes
+            .read()
+            .expect("failed to lock processes")
+            .clone()
+    }
+
+    pub fn jobs(&self) -> Vec<Arc<Job>> {
+        self.jobs.read().expect("failed to lock jobs").clone()
+    }
+
+    pub fn signal_routes(&self) -> HashMap<Signal, Vec<String>> {
+        self.signal_routes
+            .read()
+            .expect("failed to lock signal routes")
+            .clone()
+    }
+
+    pub fn reload_signal(&self) -> Option<Signal> {
+        *self
+            .reload_signal
+            .read()
+            .expect("failed to lock reload signal")
+    }
+
+    // Jobs are only scheduled once every process has been started, and
+    // reloads are refused until then.
+    pub fn finish_starting(&self) {
+        if !IS_SIGNALED.load(Ordering::Relaxed) {
+            self.jobs().iter().for_each(|job| job.start());
+        }
+        self.is_started.store(true, Ordering::Relaxed);
+    }
+
+    // Re-reads the config and applies it to the running processes: removed
+    // ones are stopped, new ones started and changed ones restarted with
+    // their new settings, while everything else keeps running. Besides the
+    // processes only signal routing is reloaded; other global settings need
+    // spot-init to be restarted.
+    pub fn reload(&self) -> Result<String, String> {
+        let result = self.apply_config();
+        match &result {
+            Ok(summary) => Event::new("reloaded")
+                .log(format_args!("reloaded {}: {}", self.config_path, summary)),
+            Err(err) => Event::new("reload_failed").log(format_args!(
+                "failed to reload {}: {}",
+                self.config_path, err
+            )),
+        }
+        result.map(|summary| format!("reloaded: {}\n", summary))
+    }
+
+    fn apply_config(&self) -> Result<String, String> {
+        let _reloading = self.reloading.lock().expect("failed to lock reload");
+        if !self.is_started.load(Ordering::Relaxed) {
+            return Err("still starting".to_owned());
+        }
+        if IS_SIGNALED.load(Ordering::Relaxed) {
+            return Err("shutting down".to_owned());
+        }
+        let config = load_config(&self.config_path, self.format)?;
+        let start_order = config.validate().and_then(|()| config.start_order())?;
+
+        let current = self.processes();
+        let current_jobs = self.jobs();
+        let current_specs = current
+            .iter()
+            .map(|process| (process.name(), process.spec()))
+            .chain(current_jobs.iter().map(|job| (job.name(), job.spec())))
+            .collect::<Vec<_>>();
+        let is_stale = |name: &str, spec: &ProcessSpec| config.processes.get(name) != Some(spec);
+        let removed = current_specs
+            .iter()
+            .filter(|(name, _)| !config.processes.contains_key(*name))
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        let changed = current_specs
+            .iter()
+            .filter(|(name, spec)| config.processes.contains_key(*name) && is_stale(name, spec))
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        let added = start_order
+            .iter()
+            .filter(|name| !current_specs.iter().any(|(current, _)| current == name))
+            .cloned()
+            .collect::<Vec<_>>();
+        let summary = format!(
+            "added {}; removed {}; changed {}",
+            describe(&added),
+            describe(&removed),
+            describe(&changed)
+        );
+        Event::new("reloading")
+            .string("added", &added.join(","))
+            .string("removed", &removed.join(","))
+            .string("changed", &changed.join(","))
+            .log(format_args!("reloading {}: {}", self.config_path, summary));
+
+        // Jobs go first as nothing depends on them, then dependents are
+        // stopped before what they depend on. A completed oneshot has nothing
+        // left to stop and is no longer counted.
+        for job in current_jobs
+            .iter()
+            .filter(|job| is_stale(job.name(), job.spec()))
+        {
+            if !job.retire() {
+                return Err("shutting down".to_owned());
+            }
+            CHILD_PROCESS_COUNT.fetch_sub(1, Ordering::Relaxed);
+        }
+        for process in current
+            .iter()
+            .rev()
+            .filter(|process| is_stale(process.name(), process.spec()))
+        {
+            if process.is_completed() {
+                continue;
+            }
+            if !process.retire() {
+                return Err("shutting down".to_owned());
+            }
+            CHILD_PROCESS_COUNT.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        // A shutdown stops whatever is in the lists once it has begun, so new
+        // processes and jobs must either be in them by then or not be added
+        // at all.
+        let mut to_start = Vec::new();
+        let mut jobs_to_start = Vec::new();
+        let processes = {
+            let mut processes = self.processes.write().expect("failed to lock processes");
+            let mut jobs = self.jobs.write().expect("failed to lock jobs");
+            if IS_SIGNALED.load(Ordering::Relaxed) {
+                return Err("shutting down".to_owned());
+            }
+            processes.clear();
+            jobs.clear();
+            for name in &start_order {
+                let spec = &config.processes[name];
+                if spec.schedule.is_some() {
+                    let unchanged = current_jobs
+                        .iter()
+                        .find(|job| job.name() == name && !is_stale(name, job.spec()));
+                    let job = match unchanged {
+                        Some(job) => job.clone(),
+                        None => {
+                            CHILD_PROCESS_COUNT.fetch_add(1, Ordering::Relaxed);
+                            let job = Arc::new(Job::new(
+                                name.clone(),
+                                spec.clone(),
+                                self.exit_tx.clone(),
+                            ));
+                            jobs_to_start.push(job.clone());
+                            job
+                        }
+                    };
+                    jobs.push(job);
+                } else {
+                    let unchanged = current
+                        .iter()
+                        .find(|process| process.name() == name && !is_stale(name, process.spec()));
+                    let process = match unchanged {
+                        Some(process) => process.clone(),
+                        None => {
+                            CHILD_PROCESS_COUNT.fetch_add(1, Ordering::Relaxed);
+                            let process = Arc::new(Process::new_stopped(
+                                name.clone(),
+                                spec.clone(),
+                                self.exit_tx.clone(),
+                            ));
+                            to_start.push(process.clone());
+                            process
+                        }
+                    };
+                    processes.push(process);
+                }
+            }
+            processes.clone()
+        };
+        *self
+            .signal_routes
+            .write()
+            .expect("failed to lock signal routes") = config.signals;
+        *self
+            .reload_signal
+            .write()
+            .expect("failed to lock reload signal") = config.reload_signal;
+
+        // Like at startup, a process waits for what it depends on to become
+        // ready. One that can't be started is left stopped.
+        let mut failures = Vec::new();
+        for process in &to_start {
+            let unready = processes
+                .iter()
+                .filter(|dependency| process.depends_on(dependency.name()))
+                .find(|dependency| !dependency.wait_ready());
+            if let Some(dependency) = unready {
+                Event::new("not_started")
+                    .process(process.name())
+                    .string("dependency", dependency.name())
+                    .log(format_args!(
+                        "not starting {}: {} is not ready",
+                        process.name(),
+                        dependency.name()
+                    ));
+                failures.push(format!(
+                    "{} not started: {} is not ready",
+                    process.name(),
+                    dependency.name()
+                ));
+                continue;
+            }
+            if let Err(err) = process.resume() {
+                failures.push(err);
+            }
+        }
+        for job in &jobs_to_start {
+            job.start();
+        }
+        if !failures.is_empty() {
+            return Err(format!("{} ({})", failures.join(", "), summary));
+        }
+        Ok(summary)
+    }
+}
+
+fn describe(names: &[String]) -> String {
+    if names.is_empty() {
+        return "none".to_owned();
+    }
+    names.join(", ")
+}
@@ -0,0 +1,109 @@
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::ptr;
+
+// Accepts `user`, `user:group`, `uid` or `uid:gid`. Without an explicit group
+// the user's primary group from the passwd database is used.
+pub fn resolve(user_spec: &str) -> io::Result<(u32, u32)> {
+    let mut split = user_spec.splitn(2, ':');
+    let user = split.next().unwrap_or_default();
+    let group = split.next();
+
+    let (uid, primary_gid) = match (lookup_user(user)?, user.parse::<u32>()) {
+        (Some(ids), _) => ids,
+        (None, Ok(uid)) => (uid, uid),
+        (None, Err(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown user `{}`", user),
+            ))
+        }
+    };
+
+    let gid = match group {
+        None => primary_gid,
+        Some(group) => match (lookup_group(group)?, group.parse::<u32>()) {
+            (Some(gid), _) => gid,
+            (None, Ok(gid)) => gid,
+            (None, Err(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("unknown group `{}`", group),
+                ))
+            }
+        },
+    };
+
+    Ok((uid, gid))
+}
+
+// Drops to the given user the way std's Command::uid and gid would, for use
+// in a pre_exec hook that has to run before the switch.
+pub fn switch(uid: u32, gid: u32) -> io::Result<()> {
+    unsafe {
+        if libc::getuid() == 0 && libc::setgroups(0, ptr::null()) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::setgid(gid) == -1 || libc::setuid(uid) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn lookup_user(user: &str) -> io::Result<Option<(u32, u32)>> {
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let rc = match user.parse::<u32>() {
+        Ok(uid) => unsafe {
+            libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result)
+        },
+        Err(_) => {
+            let name = CString::new(user)?;
+            unsafe {
+                libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut passwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            }
+        }
+    };
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+    if result.is_null() {
+        return Ok(None);
+    }
+    Ok(Some((passwd.pw_uid, passwd.pw_gid)))
+}
+
+fn lookup_group(group: &str) -> io::Result<Option<u32>> {
+    if group.parse::<u32>().is_ok() {
+        return Ok(None);
+    }
+    let mut entry: libc::group = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let name = CString::new(group)?;
+    let rc = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+    if result.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(entry.gr_gid))
+}
@@ -0,0 +1,976 @@
+use crate::position::{Position, Positions};
+use std::f64;
+use toml::value::{Table, Value};
+
+// Parses the subset of YAML that config files need into the same value
+// tree a TOML file produces: block and flow mappings and sequences, plain
+// and quoted scalars, literal and folded block scalars and comments.
+// Anchors, aliases, tags and multiple documents are rejected. TOML has no
+// null, so null mapping values are left out and rejected anywhere else.
+// Along with the value comes the line each key was written on.
+pub fn parse(contents: &str) -> Result<(Value, Positions), String> {
+    let mut parser = Parser::new(contents)?;
+    let value = match parser.peek() {
+        Some(line) => {
+            let indent = line.indent;
+            parser.node(indent)?
+        }
+        None => None,
+    };
+    if let Some(line) = parser.peek() {
+        return Err(error(line.number, "unexpected indentation"));
+    }
+    let table = match value {
+        Some(Value::Table(table)) => table,
+        None => Table::new(),
+        Some(_) => return Err("expected a mapping at the top level".to_owned()),
+    };
+    Ok((Value::Table(table), parser.positions))
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    number: usize,
+    indent: usize,
+    // The line without its indentation, comments included.
+    text: String,
+}
+
+impl Line {
+    fn is_blank(&self) -> bool {
+        self.text.is_empty() || self.text.starts_with('#')
+    }
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+    // The keys leading to the node being parsed.
+    path: Vec<String>,
+    positions: Positions,
+}
+
+fn error(line_number: usize, message: impl AsRef<str>) -> String {
+    format!("{} at line {}", message.as_ref(), line_number)
+}
+
+impl Parser {
+    fn new(contents: &str) -> Result<Self, String> {
+        let mut lines = Vec::new();
+        let mut has_content = false;
+        for (index, raw) in contents.lines().enumerate() {
+            let number = index + 1;
+            let text = raw.trim_start_matches(' ');
+            let line = Line {
+                number,
+                indent: raw.len() - text.len(),
+                text: text.trim_end().to_owned(),
+            };
+            if line.is_blank() {
+                lines.push(line);
+                continue;
+            }
+            if line.text.starts_with('\t') {
+                return Err(error(number, "tabs are not allowed in indentation"));
+            }
+            if line.indent == 0 && line.text.starts_with('%') && !has_content {
+                continue;
+            }
+            if line.indent == 0 && (line.text == "---" || line.text.starts_with("--- ")) {
+                if has_content {
+                    return Err(error(number, "multiple documents are not supported"));
+                }
+                has_content = true;
+                let rest = line.text[3..].trim_start();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(error(number, "content after `---` is not supported"));
+                }
+                continue;
+            }
+            if line.indent == 0 && line.text == "..." {
+                break;
+            }
+            has_content = true;
+            lines.push(line);
+        }
+        Ok(Self {
+            lines,
+            pos: 0,
+            path: Vec::new(),
+            positions: Positions::new(),
+        })
+    }
+
+    // The next line with content, skipping blank and comment lines.
+    fn peek(&mut self) -> Option<&Line> {
+        while self.lines.get(self.pos)?.is_blank() {
+            self.pos += 1;
+        }
+        self.lines.get(self.pos)
+    }
+
+    // A block node whose first line is the current one, at `indent`.
+    fn node(&mut self, indent: usize) -> Result<Option<Value>, String> {
+        let line = self.peek().cloned().expect("node is only called on a line");
+        if is_sequence_entry(&line.text) {
+            return self.sequence(indent).map(Some);
+        }
+        if split_key(&line.text, line.number)?.is_some() {
+            return self.mapping(indent).map(Some);
+        }
+        self.pos += 1;
+        self.inline(&line.text, line.number)
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Value, String> {
+        let mut table = Table::new();
+        while let Some(line) = self.peek() {
+            if line.indent < indent {
+                break;
+            }
+            let line = line.clone();
+            if line.indent > indent {
+                return Err(error(line.number, "unexpected indentation"));
+            }
+            if is_sequence_entry(&line.text) {
+                return Err(error(
+                    line.number,
+                    "expected `key: value`, found a list item",
+                ));
+            }
+            let (key, rest) = split_key(&line.text, line.number)?
+                .ok_or_else(|| error(line.number, "expected `key: value`"))?;
+            self.pos += 1;
+            self.path.push(key.clone());
+            self.positions.insert(&self.path, at_line(line.number));
+            let value = self.value(indent, &rest, line.number, true)?;
+            self.path.pop();
+            if table.contains_key(&key) {
+                return Err(error(line.number, format!("duplicate key `{}`", key)));
+            }
+            if let Some(value) = value {
+                table.insert(key, value);
+            }
+        }
+        Ok(Value::Table(table))
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Value, String> {
+        let mut items = Vec::new();
+        while let Some(line) = self.peek() {
+            if line.indent < indent || !is_sequence_entry(&line.text) {
+                break;
+            }
+            let line = line.clone();
+            if line.indent > indent {
+                return Err(error(line.number, "unexpected indentation"));
+            }
+            let rest = line.text[1..].trim_start();
+            let item_indent = indent + line.text.len() - rest.len();
+            let value = if is_sequence_entry(rest) || split_key(rest, line.number)?.is_some() {
+                // A nested block starting on the same line as the dash. It
+                // continues on the following lines at the same indentation.
+                self.lines[self.pos] = Line {
+                    number: line.number,
+                    indent: item_indent,
+                    text: rest.to_owned(),
+                };
+                self.node(item_indent)?
+            } else {
+                self.pos += 1;
+                self.value(indent, rest, line.number, false)?
+            };
+            items.push(
+                value.ok_or_else(|| error(line.number, "null list items are not supported"))?,
+            );
+        }
+        Ok(Value::Array(items))
+    }
+
+    // The value following a `key:` or `-`. An empty one is a nested block
+    // on the following lines, and a mapping's list may even sit at the same
+    // indentation as its key.
+    fn value(
+        &mut self,
+        indent: usize,
+        text: &str,
+        number: usize,
+        is_mapping: bool,
+    ) -> Result<Option<Value>, String> {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            return match self.peek() {
+                Some(next) if next.indent > indent => {
+                    let next_indent = next.indent;
+                    self.node(next_indent)
+                }
+                Some(next)
+                    if is_mapping && next.indent == indent && is_sequence_entry(&next.text) =>
+                {
+                    self.sequence(indent).map(Some)
+                }
+                _ => Ok(None),
+            };
+        }
+        if text.starts_with('|') || text.starts_with('>') {
+            return self.block_scalar(indent, text, number).map(Some);
+        }
+        self.inline(text, number)
+    }
+
+    // A scalar or flow collection on a single line. Flow collections may
+    // carry on over the following lines until their brackets are closed.
+    fn inline(&mut self, text: &str, number: usize) -> Result<Option<Value>, String> {
+        if let Some(c @ ('&' | '*' | '!' | '?' | '@' | '`')) = text.chars().next() {
+            return Err(error(number, format!("`{}` is not supported", c)));
+        }
+        if !text.starts_with('[') && !text.starts_with('{') {
+            let mut flow = Flow::new(text, number);
+            let value = flow.scalar(false)?;
+            flow.end()?;
+            return Ok(value);
+        }
+
+        let mut text = text.to_owned();
+        while !is_balanced(&text) {
+            match self.lines.get(self.pos) {
+                Some(line) => {
+                    text.push('\n');
+                    text.push_str(&line.text);
+                    self.pos += 1;
+                }
+                None => return Err(error(number, "unterminated flow collection")),
+            }
+        }
+        let mut flow = Flow::new(&text, number);
+        flow.path = self.path.clone();
+        let value = flow.value()?;
+        flow.end()?;
+        self.positions.extend(flow.positions);
+        Ok(value)
+    }
+
+    fn block_scalar(
+        &mut self,
+        indent: usize,
+        header: &str,
+        number: usize,
+    ) -> Result<Value, String> {
+        let header = strip_comment(header);
+        let (is_folded, chomping) = match header {
+            "|" => (false, ""),
+            "|-" | "|+" => (false, &header[1..]),
+            ">" => (true, ""),
+            ">-" | ">+" => (true, &header[1..]),
+            _ => {
+                return Err(error(
+                    number,
+                    format!("unsupported block scalar `{}`", header),
+                ))
+            }
+        };
+
+        let mut block_indent = None;
+        let mut lines = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.text.is_empty() {
+                lines.push(String::new());
+            } else {
+                if line.indent <= indent || block_indent.is_some_and(|block| line.indent < block) {
+                    break;
+                }
+                let block_indent = *block_indent.get_or_insert(line.indent);
+                lines.push(format!(
+                    "{}{}",
+                    " ".repeat(line.indent - block_indent),
+                    line.text
+                ));
+            }
+            self.pos += 1;
+        }
+        let trailing_blank_lines = lines
+            .iter()
+            .rev()
+            .take_while(|line| line.is_empty())
+            .count();
+        lines.truncate(lines.len() - trailing_blank_lines);
+
+        let mut string = if is_folded {
+            let mut folded = String::new();
+            let mut needs_space = false;
+            for line in &lines {
+                if line.is_empty() {
+                    folded.push('\n');
+                    needs_space = false;
+                } else {
+                    if needs_space {
+                        folded.push(' ');
+                    }
+                    folded.push_str(line);
+                    needs_space = true;
+                }
+            }
+            folded
+        } else {
+            lines.join("\n")
+        };
+        match chomping {
+            "-" => {}
+            "+" => string.push_str(&"\n".repeat(trailing_blank_lines + 1)),
+            _ if !string.is_empty() => string.push('\n'),
+            _ => {}
+        }
+        Ok(Value::String(string))
+    }
+}
+
+fn at_line(line: usize) -> Position {
+    Position { line, column: None }
+}
+
+fn is_sequence_entry(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+// Splits `key: rest` off a line, or returns None if the line is not a
+// mapping entry.
+fn split_key(text: &str, number: usize) -> Result<Option<(String, String)>, String> {
+    if text.starts_with('"') || text.starts_with('\'') {
+        let mut flow = Flow::new(text, number);
+        let key = flow.quoted()?;
+        let rest: String = flow.chars[flow.pos..].iter().collect();
+        let rest = rest.trim_start();
+        return Ok(rest
+            .strip_prefix(':')
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+            .map(|rest| (key, rest.to_owned())));
+    }
+    if text.starts_with('[') || text.starts_with('{') || text.starts_with('#') {
+        return Ok(None);
+    }
+    let key_text = strip_comment(text);
+    let separator = key_text.char_indices().find(|&(index, c)| {
+        c == ':'
+            && key_text[index + 1..]
+                .chars()
+                .next()
+                .is_none_or(|c| c == ' ')
+    });
+    Ok(separator.map(|(index, _)| {
+        (
+            text[..index].trim_end().to_owned(),
+            text[index + 1..].to_owned(),
+        )
+    }))
+}
+
+// Removes a trailing comment from a line without quotes.
+fn strip_comment(text: &str) -> &str {
+    let end = text.find(" #").unwrap_or(text.len());
+    text[..end].trim_end()
+}
+
+// Whether every bracket in a flow collection is closed, ignoring the ones in
+// quotes and comments.
+fn is_balanced(text: &str) -> bool {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut previous = ' ';
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') if previous == ' ' || previous == '\n' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => depth -= 1,
+            _ => {}
+        }
+        previous = c;
+    }
+    depth <= 0
+}
+
+// Parses flow collections and scalars within a single (possibly joined)
+// piece of text.
+struct Flow {
+    chars: Vec<char>,
+    pos: usize,
+    number: usize,
+    path: Vec<String>,
+    positions: Positions,
+}
+
+impl Flow {
+    fn new(text: &str, number: usize) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+            number,
+            path: Vec::new(),
+            positions: Positions::new(),
+        }
+    }
+
+    fn error(&self, message: impl AsRef<str>) -> String {
+        error(self.line(), message)
+    }
+
+    fn line(&self) -> usize {
+        self.number
+            + self.chars[..self.pos.min(self.chars.len())]
+                .iter()
+                .filter(|&&c| c == '\n')
+                .count()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\n' => self.pos += 1,
+                '#' if self.pos == 0 || matches!(self.chars[self.pos - 1], ' ' | '\n') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // Only whitespace and comments may follow a value.
+    fn end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) => Err(self.error(format!("unexpected `{}`", c))),
+            None => Ok(()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Option<Value>, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('[') => self.sequence().map(Some),
+            Some('{') => self.mapping().map(Some),
+            _ => self.scalar(true),
+        }
+    }
+
+    fn sequence(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            match self.value()? {
+                Some(value) => items.push(value),
+                None => return Err(self.error("null list items are not supported")),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn mapping(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut table = Table::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Value::Table(table));
+            }
+            self.skip_whitespace();
+            let key_line = self.line();
+            let key = match self.scalar(true)? {
+                Some(Value::String(key)) => key,
+                Some(key) => key.to_string(),
+                None => return Err(self.error("expected a key")),
+            };
+            self.skip_whitespace();
+            self.path.push(key.clone());
+            self.positions.insert(&self.path, at_line(key_line));
+            let value = if self.peek() == Some(':') {
+                self.pos += 1;
+                self.value()?
+            } else {
+                None
+            };
+            self.path.pop();
+            if table.contains_key(&key) {
+                return Err(self.error(format!("duplicate key `{}`", key)));
+            }
+            if let Some(value) = value {
+                table.insert(key, value);
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    // A quoted or plain scalar. Inside a flow collection plain scalars end
+    // at `,`, brackets and `: `.
+    fn scalar(&mut self, in_flow: bool) -> Result<Option<Value>, String> {
+        self.skip_whitespace();
+        if let Some('"' | '\'') = self.peek() {
+            return self.quoted().map(|string| Some(Value::String(string)));
+        }
+        if let Some(c @ ('&' | '*' | '!')) = self.peek() {
+            return Err(self.error(format!("`{}` is not supported", c)));
+        }
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let next = self.chars.get(self.pos + 1).copied();
+            let ends = match c {
+                ',' | '[' | ']' | '{' | '}' => in_flow,
+                ':' => {
+                    next.is_none_or(|next| next == ' ' || next == '\n' || in_flow && next == ',')
+                }
+                '#' => self.pos > start && self.chars[self.pos - 1] == ' ',
+                '\n' => !in_flow,
+                _ => false,
+            };
+            if ends {
+                break;
+            }
+            self.pos += 1;
+        }
+        let plain: String = self.chars[start..self.pos].iter().collect();
+        Ok(plain_scalar(plain.trim()))
+    }
+
+    fn quoted(&mut self) -> Result<String, String> {
+        let quote = self.peek().expect("quoted is only called on a quote");
+        self.pos += 1;
+        let mut string = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match (quote, c) {
+                ('\'', '\'') if self.peek() == Some('\'') => {
+                    self.pos += 1;
+                    string.push('\'');
+                }
+                (_, '\n') => string.push(' '),
+                (quote, c) if c == quote => return Ok(string),
+                ('"', '\\') => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match escape {
+                        '0' => string.push('\0'),
+                        'a' => string.push('\u{7}'),
+                        'b' => string.push('\u{8}'),
+                        't' => string.push('\t'),
+                        'n' => string.push('\n'),
+                        'v' => string.push('\u{b}'),
+                        'f' => string.push('\u{c}'),
+                        'r' => string.push('\r'),
+                        'e' => string.push('\u{1b}'),
+                        ' ' | '"' | '/' | '\\' => string.push(escape),
+                        'x' | 'u' | 'U' => {
+                            let length = match escape {
+                                'x' => 2,
+                                'u' => 4,
+                                _ => 8,
+                            };
+                            let digits: String =
+                                self.chars.iter().skip(self.pos).take(length).collect();
+                            let c = u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .filter(|_| digits.len() == length)
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid escape"))?;
+                            self.pos += length;
+                            string.push(c);
+                        }
+                        _ => return Err(self.error(format!("invalid escape `\\{}`", escape))),
+                    }
+                }
+                (_, c) => string.push(c),
+            }
+        }
+    }
+}
+
+// Resolves a plain scalar the way YAML 1.2's core schema does.
+fn plain_scalar(text: &str) -> Option<Value> {
+    let value = match text {
+        "" | "~" | "null" | "Null" | "NULL" => return None,
+        "true" | "True" | "TRUE" => Value::Boolean(true),
+        "false" | "False" | "FALSE" => Value::Boolean(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => Value::Float(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => Value::Float(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => Value::Float(f64::NAN),
+        _ => {
+            let unsigned = text.trim_start_matches(['-', '+']);
+            let integer = if let Some(hex) = text.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16).ok()
+            } else if let Some(octal) = text.strip_prefix("0o") {
+                i64::from_str_radix(octal, 8).ok()
+            } else if !unsigned.is_empty() && unsigned.chars().all(|c| c.is_ascii_digit()) {
+                text.parse().ok()
+            } else {
+                None
+            };
+            let is_float = unsigned.chars().any(|c| c.is_ascii_digit())
+                && unsigned
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '-' | '+'));
+            match integer {
+                Some(integer) => Value::Integer(integer),
+                None => match text.parse() {
+                    Ok(float) if is_float => Value::Float(float),
+                    _ => Value::String(text.to_owned()),
+                },
+            }
+        }
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(yaml: &str) -> Value {
+        parse(yaml).unwrap().0
+    }
+
+    fn toml(toml: &str) -> Value {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn error(yaml: &str) -> String {
+        parse(yaml).unwrap_err()
+    }
+
+    #[test]
+    fn block_collections() {
+        let yaml = "
+processes:
+  web:
+    command:
+      - python3
+      - -m
+      - http.server
+    depends_on:
+    - db
+  db:
+    command: postgres
+";
+        let expected = r#"
+[processes.web]
+command = ["python3", "-m", "http.server"]
+depends_on = ["db"]
+[processes.db]
+command = "postgres"
+"#;
+        assert_eq!(value(yaml), toml(expected));
+    }
+
+    #[test]
+    fn mappings_in_sequences() {
+        let yaml = "
+items:
+  - name: a
+    port: 1
+  - name: b
+lists:
+  - - nested
+";
+        let expected = r#"
+items = [{ name = "a", port = 1 }, { name = "b" }]
+lists = [["nested"]]
+"#;
+        assert_eq!(value(yaml), toml(expected));
+    }
+
+    #[test]
+    fn flow_collections() {
+        let yaml = "
+env: { A: 1, B: two, 'C D': \"e, f\" }
+argv: [sh, -c, 'echo {}']
+nested: [{ a: [1, 2] }, {}]
+multiline: [
+  a,  # first
+  b,
+]
+";
+        let expected = r#"
+env = { A = 1, B = "two", "C D" = "e, f" }
+argv = ["sh", "-c", "echo {}"]
+nested = [{ a = [1, 2] }, {}]
+multiline = ["a", "b"]
+"#;
+        assert_eq!(value(yaml), toml(expected));
+    }
+
+    #[test]
+    fn scalars() {
+        let yaml = "
+int: 42
+negative: -7
+hex: 0x1f
+octal: 0o17
+float: 2.5
+exponent: 1e3
+yes: true
+no: False
+string: hello world
+version: 1.2.3
+colon: a:b
+url: http://localhost:80/
+";
+        let expected = r#"
+int = 42
+negative = -7
+hex = 31
+octal = 15
+float = 2.5
+exponent = 1000.0
+yes = true
+no = false
+string = "hello world"
+version = "1.2.3"
+colon = "a:b"
+url = "http://localhost:80/"
+"#;
+        assert_eq!(value(yaml), toml(expected));
+    }
+
+    #[test]
+    fn special_floats() {
+        let value = value("a: .inf\nb: -.Inf\nc: .nan\n");
+        assert_eq!(value["a"].as_float(), Some(f64::INFINITY));
+        assert_eq!(value["b"].as_float(), Some(f64::NEG_INFINITY));
+        assert!(value["c"].as_float().unwrap().is_nan());
+    }
+
+    #[test]
+    fn nulls_are_left_out_of_mappings() {
+        assert_eq!(value("a: ~\nb: null\nc:\nd: 1\n"), toml("d = 1"));
+        assert_eq!(value("m: { a: , b: 1 }\n"), toml("m = { b = 1 }"));
+    }
+
+    #[test]
+    fn null_list_items_are_rejected() {
+        assert_eq!(
+            error("a:\n  - 1\n  -\n"),
+            "null list items are not supported at line 3"
+        );
+        assert_eq!(
+            error("a: [1, null]\n"),
+            "null list items are not supported at line 1"
+        );
+    }
+
+    #[test]
+    fn literal_block_scalars() {
+        let yaml = "
+clip: |
+  line one
+    indented
+
+  line three
+
+
+strip: |-
+  text
+
+keep: |+
+  text
+
+end: 1
+";
+        let value = value(yaml);
+        assert_eq!(
+            value["clip"].as_str(),
+            Some("line one\n  indented\n\nline three\n")
+        );
+        assert_eq!(value["strip"].as_str(), Some("text"));
+        assert_eq!(value["keep"].as_str(), Some("text\n\n"));
+        assert_eq!(value["end"].as_integer(), Some(1));
+    }
+
+    #[test]
+    fn folded_block_scalars() {
+        let yaml = "
+folded: >
+  one
+  two
+
+  three
+stripped: >- # comment
+  a
+  b
+";
+        let value = value(yaml);
+        assert_eq!(value["folded"].as_str(), Some("one two\nthree\n"));
+        assert_eq!(value["stripped"].as_str(), Some("a b"));
+    }
+
+    #[test]
+    fn unsupported_block_scalar_headers() {
+        assert_eq!(
+            error("a: |2\n  text\n"),
+            "unsupported block scalar `|2` at line 1"
+        );
+    }
+
+    #[test]
+    fn double_quoted_escapes() {
+        let value = value(r#"a: "tab\there \"q\" \\ \x41\u00e9\U0001F600 \/""#);
+        assert_eq!(value["a"].as_str(), Some("tab\there \"q\" \\ Aé😀 /"));
+        assert_eq!(error(r#"a: "\q""#), "invalid escape `\\q` at line 1");
+        assert_eq!(error("a: \"\\u12\"\n"), "invalid escape at line 1");
+    }
+
+    #[test]
+    fn single_quoted_strings() {
+        let value = value("a: 'it''s \\n # not a comment'\nb: '42'\n");
+        assert_eq!(value["a"].as_str(), Some("it's \\n # not a comment"));
+        assert_eq!(value["b"].as_str(), Some("42"));
+    }
+
+    #[test]
+    fn quoted_strings_fold_lines() {
+        let value = value("a: [\"one\ntwo\"]\n");
+        assert_eq!(value["a"][0].as_str(), Some("one two"));
+    }
+
+    #[test]
+    fn quoted_keys() {
+        assert_eq!(
+            value("\"a b\": 1\n'c: d': 2\n"),
+            toml(
+                r#""a b" = 1
+"c: d" = 2"#
+            )
+        );
+    }
+
+    #[test]
+    fn unterminated_strings() {
+        assert_eq!(error("a: \"open\n"), "unterminated string at line 1");
+        assert_eq!(
+            error("a: [1, 2\n"),
+            "unterminated flow collection at line 1"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let yaml = "
+# leading
+a: 1 # trailing
+b: 'x # y' # after quotes
+c: x#y
+d: [1, 2] # after flow
+";
+        assert_eq!(
+            value(yaml),
+            toml(
+                r#"a = 1
+b = "x # y"
+c = "x#y"
+d = [1, 2]"#
+            )
+        );
+    }
+
+    #[test]
+    fn document_markers() {
+        assert_eq!(value("%YAML 1.2\n---\na: 1\n...\nignored\n"), toml("a = 1"));
+        assert_eq!(
+            error("a: 1\n---\nb: 2\n"),
+            "multiple documents are not supported at line 2"
+        );
+        assert_eq!(
+            error("--- a: 1\n"),
+            "content after `---` is not supported at line 1"
+        );
+    }
+
+    #[test]
+    fn empty_documents() {
+        assert_eq!(value(""), toml(""));
+        assert_eq!(value("# only a comment\n"), toml(""));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert_eq!(error("a: 1\nb: 2\na: 3\n"), "duplicate key `a` at line 3");
+        assert_eq!(error("m: { a: 1, a: 2 }\n"), "duplicate key `a` at line 1");
+    }
+
+    #[test]
+    fn rejected_features() {
+        assert_eq!(error("a: &anchor 1\n"), "`&` is not supported at line 1");
+        assert_eq!(error("a: *alias\n"), "`*` is not supported at line 1");
+        assert_eq!(error("a: !!str 1\n"), "`!` is not supported at line 1");
+        assert_eq!(error("a: [*alias]\n"), "`*` is not supported at line 1");
+        assert_eq!(
+            error("a:\n\t- 1\n"),
+            "tabs are not allowed in indentation at line 2"
+        );
+        assert_eq!(error("- 1\n- 2\n"), "expected a mapping at the top level");
+    }
+
+    #[test]
+    fn bad_indentation() {
+        assert_eq!(error("a: 1\n  b: 2\n"), "unexpected indentation at line 2");
+        assert_eq!(
+            error("a:\n  b: 1\n   c: 2\n"),
+            "unexpected indentation at line 3"
+        );
+        assert_eq!(
+            error("a:\n  b: 1\n  - c\n"),
+            "expected `key: value`, found a list item at line 3"
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let yaml = "
+processes:
+  web:
+    command: run
+    env: { A: 1,
+      B: 2 }
+";
+        let (_, positions) = parse(yaml).unwrap();
+        let line = |path: &str| positions.get(path).map(|position| position.line);
+        assert_eq!(line("processes"), Some(2));
+        assert_eq!(line("processes.web"), Some(3));
+        assert_eq!(line("processes.web.command"), Some(4));
+        assert_eq!(line("processes.web.env.A"), Some(5));
+        assert_eq!(line("processes.web.env.B"), Some(6));
+    }
+}